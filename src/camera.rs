@@ -0,0 +1,166 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{
+    levels::{level_bounds, LevelStreaming},
+    physics::Velocity,
+    player::Player,
+};
+
+/// What cameras with `CameraFollow` keep in view
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct CameraTarget;
+
+/// How a camera follows its target vertically while the target is in the air
+#[derive(Copy, Clone, PartialEq, Debug, Reflect)]
+pub enum AirborneFollow {
+    /// Keeps the camera height until the target lands, or until it comes within `margin` of the
+    /// top or bottom of the view
+    Lock { margin: f32 },
+    /// Follows inside a deadzone of this half height instead, so small jumps don't bob the camera
+    Deadzone(f32),
+}
+
+impl Default for AirborneFollow {
+    fn default() -> Self {
+        Self::Lock { margin: 32. }
+    }
+}
+
+/// Makes a camera follow the `CameraTarget` while staying inside the current level
+#[derive(Copy, Clone, PartialEq, Debug, Component, Reflect)]
+#[reflect(Component)]
+pub struct CameraFollow {
+    /// Half size of the box around the camera center the target moves in without moving the camera
+    pub deadzone: Vec2,
+    pub airborne: AirborneFollow,
+    /// Whether the target got too close to the edge of the view during this jump, so the camera
+    /// follows it again until it lands
+    pub lock_released: bool,
+    /// How quickly the camera catches up, higher is snappier
+    pub smoothing: f32,
+    /// Seconds of horizontal velocity the camera looks ahead of the target
+    pub lookahead: f32,
+    pub max_lookahead: f32,
+}
+
+impl Default for CameraFollow {
+    fn default() -> Self {
+        Self {
+            deadzone: Vec2::new(16., 24.),
+            airborne: default(),
+            lock_released: false,
+            smoothing: 8.,
+            lookahead: 0.25,
+            max_lookahead: 32.,
+        }
+    }
+}
+
+/// Keeps a view of `half_size` centered at `center` inside `bounds`, or centered on them when
+/// they are smaller than the view
+pub fn clamp_to_bounds(center: Vec2, half_size: Vec2, bounds: Rect) -> Vec2 {
+    let min = bounds.min + half_size;
+    let max = bounds.max - half_size;
+
+    Vec2::new(
+        if min.x <= max.x {
+            center.x.clamp(min.x, max.x)
+        } else {
+            bounds.center().x
+        },
+        if min.y <= max.y {
+            center.y.clamp(min.y, max.y)
+        } else {
+            bounds.center().y
+        },
+    )
+}
+
+pub fn follow_camera_target(
+    time: Res<Time>,
+    mut cameras: Query<(&mut CameraFollow, &OrthographicProjection, &mut Transform)>,
+    targets: Query<
+        (&Transform, Option<&Velocity>, Option<&Player>),
+        (With<CameraTarget>, Without<CameraFollow>),
+    >,
+    worlds: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    streaming: Res<LevelStreaming>,
+) {
+    let Ok((target, velocity, player)) = targets.get_single() else {
+        return;
+    };
+
+    let bounds = streaming.current.as_ref().and_then(|current| {
+        worlds
+            .iter()
+            .filter_map(|handle| ldtk_project_assets.get(handle))
+            .flat_map(|ldtk_project| ldtk_project.iter_raw_levels())
+            .find(|level| level.iid == current.get().as_str())
+            .map(level_bounds)
+    });
+
+    let airborne = player.is_some_and(|player| !player.grounded && !player.climbing);
+
+    for (mut follow, projection, mut transform) in &mut cameras {
+        let camera = transform.translation.xy();
+        let half_view = projection.area.half_size();
+
+        let lookahead = velocity.map_or(0., |velocity| {
+            (velocity.value.x * follow.lookahead).clamp(-follow.max_lookahead, follow.max_lookahead)
+        });
+        let focus = target.translation.xy() + Vec2::new(lookahead, 0.);
+
+        if !airborne {
+            follow.lock_released = false;
+        }
+
+        let mut deadzone = follow.deadzone;
+        let mut lock_y = false;
+
+        if airborne {
+            match follow.airborne {
+                AirborneFollow::Lock { margin } => {
+                    if (focus.y - camera.y).abs() > half_view.y - margin {
+                        follow.lock_released = true;
+                    }
+
+                    lock_y = !follow.lock_released;
+                }
+                AirborneFollow::Deadzone(half_height) => deadzone.y = half_height,
+            }
+        }
+
+        // only move by as much as the focus left the deadzone
+        let mut goal = camera + focus - focus.clamp(camera - deadzone, camera + deadzone);
+
+        if lock_y {
+            goal.y = camera.y;
+        }
+
+        if let Some(bounds) = bounds {
+            goal = clamp_to_bounds(goal, half_view, bounds);
+        }
+
+        let t = 1. - (-follow.smoothing * time.delta_seconds()).exp();
+        let center = camera.lerp(goal, t);
+
+        transform.translation.x = center.x;
+        transform.translation.y = center.y;
+    }
+}
+
+pub struct CameraFollowPlugin;
+
+impl Plugin for CameraFollowPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<CameraFollow>()
+            .register_type::<AirborneFollow>()
+            .add_systems(
+                PostUpdate,
+                // after the physics ticks of this frame, so the camera never lags a tick behind
+                follow_camera_target.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
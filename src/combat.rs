@@ -0,0 +1,454 @@
+use bevy::{
+    math::bounding::{Aabb2d, IntersectsVolume},
+    prelude::*,
+};
+
+use crate::{levels::level_ready, physics::*, rewind::rewinding};
+
+/// Who an entity fights for, hitboxes never hurt the hurtboxes of their own team
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash, Component, Reflect)]
+#[reflect(Component, Default)]
+pub enum Team {
+    Player,
+    Enemy,
+    /// Spikes and other parts of the level, which hurt every team but their own
+    #[default]
+    Environment,
+}
+
+/// Deals damage to the hurtboxes of other teams it overlaps
+///
+/// Combat shapes are separate from the `Collider`, what hurts and what gets hurt doesn't have to
+/// match what blocks movement. Custom shapes can only hit boxes, or be hit by them
+#[derive(Component, Clone)]
+pub struct Hitbox {
+    shape: SharedShape,
+    /// Where the shape is centered, relative to the entity
+    pub offset: Vec2,
+    /// Speed victims are knocked back with
+    pub knockback: f32,
+}
+
+impl Default for Hitbox {
+    fn default() -> Self {
+        Self::new(SharedShape::aabb(Vec2::splat(1.)), 0.)
+    }
+}
+
+impl Hitbox {
+    pub fn new(shape: SharedShape, knockback: f32) -> Self {
+        Self {
+            shape,
+            offset: Vec2::ZERO,
+            knockback,
+        }
+    }
+
+    /// Whether the hitbox overlaps `hurtbox`, given where their entities are
+    pub fn overlaps(&self, position: Vec2, hurtbox: &Hurtbox, hurtbox_position: Vec2) -> bool {
+        shapes_overlap(
+            &self.shape,
+            position + self.offset,
+            &hurtbox.shape,
+            hurtbox_position + hurtbox.offset,
+        )
+    }
+}
+
+/// Can be hit by the hitboxes of other teams, with the same shapes as a `Hitbox`
+#[derive(Component, Clone)]
+pub struct Hurtbox {
+    shape: SharedShape,
+    /// Where the shape is centered, relative to the entity
+    pub offset: Vec2,
+    /// Fixed ticks nothing can hit again after each hit
+    pub invulnerability: u32,
+}
+
+impl Default for Hurtbox {
+    fn default() -> Self {
+        Self::new(SharedShape::aabb(Vec2::splat(1.)), 0)
+    }
+}
+
+impl Hurtbox {
+    pub fn new(shape: SharedShape, invulnerability: u32) -> Self {
+        Self {
+            shape,
+            offset: Vec2::ZERO,
+            invulnerability,
+        }
+    }
+}
+
+/// Fixed ticks left until the hurtbox can be hit again
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct Invulnerable(pub u32);
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DamageEvent {
+    pub attacker: Entity,
+    pub victim: Entity,
+    /// Away from the hitbox, which way the victim is knocked back
+    pub direction: Vec2,
+}
+
+/// Sets `velocity` to move along `direction` at `speed`, the usual response to a hit
+pub fn knock_back(velocity: &mut Velocity, direction: Vec2, speed: f32) {
+    velocity.value = direction * speed;
+}
+
+/// The segment a ray covers, as a box with no width
+fn ray_segment(ray: &RayCast, position: Vec2) -> Aabb2d {
+    let end = position + ray.direction.as_vec2() * ray.length;
+
+    Aabb2d {
+        min: position.min(end),
+        max: position.max(end),
+    }
+}
+
+/// Whether two combat shapes overlap, custom shapes never overlap anything but boxes
+fn shapes_overlap(a: &SharedShape, a_position: Vec2, b: &SharedShape, b_position: Vec2) -> bool {
+    match (a.as_typed_shape(), b.as_typed_shape()) {
+        (TypedShape::Compound(a), _) => a
+            .shapes
+            .iter()
+            .any(|(offset, a)| shapes_overlap(a, a_position + *offset, b, b_position)),
+        (_, TypedShape::Compound(b)) => b
+            .shapes
+            .iter()
+            .any(|(offset, b)| shapes_overlap(a, a_position, b, b_position + *offset)),
+        (_, TypedShape::Aabb(b)) => a.collides(a_position, &b.aabb(b_position)),
+        (TypedShape::Aabb(a), _) => b.collides(b_position, &a.aabb(a_position)),
+        (TypedShape::Ball(a), TypedShape::Ball(b)) => a
+            .bounding_circle(a_position)
+            .intersects(&b.bounding_circle(b_position)),
+        (TypedShape::Ball(ball), TypedShape::Ray(ray)) => ray
+            .ray_cast(b_position)
+            .intersects(&ball.bounding_circle(a_position)),
+        (TypedShape::Ray(ray), TypedShape::Ball(ball)) => ray
+            .ray_cast(a_position)
+            .intersects(&ball.bounding_circle(b_position)),
+        // rays are axis aligned, so their segments cross exactly when their boxes do
+        (TypedShape::Ray(a), TypedShape::Ray(b)) => {
+            ray_segment(&a, a_position).intersects(&ray_segment(&b, b_position))
+        }
+        _ => {
+            warn_once!("custom combat shapes can only be tested against boxes");
+            false
+        }
+    }
+}
+
+pub fn tick_invulnerability(
+    mut commands: Commands,
+    mut invulnerable: Query<(Entity, &mut Invulnerable)>,
+) {
+    for (entity, mut invulnerable) in &mut invulnerable {
+        invulnerable.0 = invulnerable.0.saturating_sub(1);
+
+        if invulnerable.0 == 0 {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+/// Sends a `DamageEvent` for every hurtbox overlapping a hitbox of another team, at most one
+/// per hurtbox and tick
+pub fn detect_hits(
+    mut commands: Commands,
+    mut ev_damage: EventWriter<DamageEvent>,
+    hitboxes: Query<(Entity, &Hitbox, &Team, &GlobalTransform, Option<&StableId>)>,
+    hurtboxes: Query<
+        (Entity, &Hurtbox, &Team, &GlobalTransform, Option<&StableId>),
+        Without<Invulnerable>,
+    >,
+) {
+    // sorted so the same overlaps always produce the same hits on every peer. Hitboxes spawned
+    // from tiles have no `StableId`, but they never share a position
+    let by_id_and_position = |(a_id, a): &(Option<&StableId>, Vec2), (b_id, b): &(_, Vec2)| {
+        a_id.cmp(b_id)
+            .then(a.x.total_cmp(&b.x))
+            .then(a.y.total_cmp(&b.y))
+    };
+
+    let mut hitboxes = hitboxes
+        .iter()
+        .map(|(entity, hitbox, team, transform, id)| {
+            let position = transform.translation().xy() + hitbox.offset;
+            (entity, hitbox, team, (id, position))
+        })
+        .collect::<Vec<_>>();
+    hitboxes.sort_by(|(.., a), (.., b)| by_id_and_position(a, b));
+
+    let mut hurtboxes = hurtboxes
+        .iter()
+        .map(|(entity, hurtbox, team, transform, id)| {
+            let position = transform.translation().xy() + hurtbox.offset;
+            (entity, hurtbox, team, (id, position))
+        })
+        .collect::<Vec<_>>();
+    hurtboxes.sort_by(|(.., a), (.., b)| by_id_and_position(a, b));
+
+    for (victim, hurtbox, team, (_, position)) in hurtboxes {
+        let hit = hitboxes.iter().find(
+            |(attacker, hitbox, attacker_team, (_, attacker_position))| {
+                *attacker != victim
+                    && *attacker_team != team
+                    && shapes_overlap(&hitbox.shape, *attacker_position, &hurtbox.shape, position)
+            },
+        );
+
+        let Some(&(attacker, _, _, (_, attacker_position))) = hit else {
+            continue;
+        };
+
+        ev_damage.send(DamageEvent {
+            attacker,
+            victim,
+            // straight up when both are centered on the same spot
+            direction: (position - attacker_position)
+                .try_normalize()
+                .unwrap_or(Vec2::Y),
+        });
+
+        if hurtbox.invulnerability > 0 {
+            commands
+                .entity(victim)
+                .insert(Invulnerable(hurtbox.invulnerability));
+        }
+    }
+}
+
+pub fn apply_knockback(
+    mut ev_damage: EventReader<DamageEvent>,
+    hitboxes: Query<&Hitbox>,
+    mut victims: Query<&mut Velocity>,
+) {
+    for ev in ev_damage.read() {
+        let Ok(hitbox) = hitboxes.get(ev.attacker) else {
+            continue;
+        };
+
+        if hitbox.knockback == 0. {
+            continue;
+        }
+
+        if let Ok(mut velocity) = victims.get_mut(ev.victim) {
+            knock_back(&mut velocity, ev.direction, hitbox.knockback);
+        }
+    }
+}
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Team>()
+            .snapshot_component::<Invulnerable>()
+            .add_event::<DamageEvent>()
+            .add_systems(
+                FixedUpdate,
+                // hits are tested where the simulation left everything this tick
+                (tick_invulnerability, detect_hits, apply_knockback)
+                    .chain()
+                    .after(Physics::Simulation)
+                    .run_if(level_ready.and_then(not(rewinding))),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlap(a: SharedShape, a_position: Vec2, b: SharedShape, b_position: Vec2) -> bool {
+        let forward = shapes_overlap(&a, a_position, &b, b_position);
+        assert_eq!(forward, shapes_overlap(&b, b_position, &a, a_position));
+        forward
+    }
+
+    #[test]
+    fn overlaps_every_pair_of_built_in_shapes() {
+        let ball = || SharedShape::ball(4.);
+        let ray = |direction| SharedShape::ray_cast(direction, 10.);
+
+        assert!(overlap(ball(), Vec2::ZERO, ball(), Vec2::new(7., 0.)));
+        assert!(!overlap(ball(), Vec2::ZERO, ball(), Vec2::new(9., 0.)));
+
+        assert!(overlap(
+            ray(Cardinal::East),
+            Vec2::ZERO,
+            ball(),
+            Vec2::new(12., 0.)
+        ));
+        assert!(!overlap(
+            ray(Cardinal::East),
+            Vec2::ZERO,
+            ball(),
+            Vec2::new(0., 6.)
+        ));
+
+        // crossing, then side by side
+        assert!(overlap(
+            ray(Cardinal::East),
+            Vec2::ZERO,
+            ray(Cardinal::South),
+            Vec2::new(5., 5.)
+        ));
+        assert!(!overlap(
+            ray(Cardinal::East),
+            Vec2::ZERO,
+            ray(Cardinal::East),
+            Vec2::new(0., 5.)
+        ));
+
+        let compound = SharedShape::compound([(Vec2::new(20., 0.), ball())]);
+        assert!(overlap(
+            compound.clone(),
+            Vec2::ZERO,
+            ball(),
+            Vec2::new(24., 0.)
+        ));
+        assert!(!overlap(compound, Vec2::ZERO, ball(), Vec2::ZERO));
+    }
+
+    #[test]
+    fn overlaps_custom_shapes_with_boxes() {
+        let custom = || SharedShape::custom(Aabb::new(Vec2::splat(2.)));
+        let aabb = SharedShape::aabb(Vec2::splat(2.));
+
+        assert!(overlap(
+            custom(),
+            Vec2::ZERO,
+            aabb.clone(),
+            Vec2::new(2.5, 0.)
+        ));
+        assert!(!overlap(custom(), Vec2::ZERO, aabb, Vec2::new(5., 0.)));
+
+        // nothing else can be tested against them
+        assert!(!overlap(
+            custom(),
+            Vec2::ZERO,
+            SharedShape::ball(4.),
+            Vec2::ZERO
+        ));
+        assert!(!overlap(custom(), Vec2::ZERO, custom(), Vec2::ZERO));
+
+        let hitbox = Hitbox::new(custom(), 0.);
+        let hurtbox = Hurtbox::new(SharedShape::aabb(Vec2::ONE), 0);
+        assert!(hitbox.overlaps(Vec2::ZERO, &hurtbox, Vec2::new(1., 1.)));
+    }
+
+    fn tick(app: &mut App) -> Vec<DamageEvent> {
+        app.update();
+        app.world_mut()
+            .resource_mut::<Events<DamageEvent>>()
+            .drain()
+            .collect()
+    }
+
+    fn hits(events: &[DamageEvent], victim: Entity) -> Vec<DamageEvent> {
+        events
+            .iter()
+            .filter(|ev| ev.victim == victim)
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn hits_other_teams_once_per_tick() {
+        let mut app = App::new();
+        app.add_event::<DamageEvent>().add_systems(
+            Update,
+            (tick_invulnerability, detect_hits, apply_knockback).chain(),
+        );
+
+        let world = app.world_mut();
+        let at = |x, y| GlobalTransform::from_translation(Vec3::new(x, y, 0.));
+
+        let attacker = world
+            .spawn((
+                StableId(1),
+                Team::Enemy,
+                Hitbox::new(SharedShape::aabb(Vec2::splat(8.)), 100.),
+                at(0., 0.),
+            ))
+            .id();
+        // also overlaps the victim, but comes after the attacker
+        world.spawn((
+            StableId(2),
+            Team::Environment,
+            Hitbox::new(SharedShape::aabb(Vec2::splat(2.)), 50.),
+            at(8., 0.),
+        ));
+        let ally = world
+            .spawn((
+                Team::Enemy,
+                Hurtbox::new(SharedShape::aabb(Vec2::splat(4.)), 0),
+                Velocity::default(),
+                at(0., 0.),
+            ))
+            .id();
+        let victim = world
+            .spawn((
+                Team::Player,
+                Hurtbox::new(SharedShape::aabb(Vec2::splat(4.)), 2),
+                Velocity::default(),
+                at(4., 0.),
+            ))
+            .id();
+        // right on top of the attacker, with no way to tell which way it was hit from
+        let centered = world
+            .spawn((
+                Team::Player,
+                Hurtbox::new(SharedShape::aabb(Vec2::ONE), 0),
+                Velocity::default(),
+                at(0., 0.),
+            ))
+            .id();
+
+        let events = tick(&mut app);
+
+        assert!(hits(&events, ally).is_empty());
+
+        let victim_hits = hits(&events, victim);
+        assert_eq!(victim_hits.len(), 1);
+        assert_eq!(victim_hits[0].attacker, attacker);
+        assert_eq!(victim_hits[0].direction, Vec2::X);
+
+        let centered_hits = hits(&events, centered);
+        assert_eq!(centered_hits.len(), 1);
+        assert_eq!(centered_hits[0].direction, Vec2::Y);
+
+        let world = app.world();
+        assert_eq!(
+            world.get::<Velocity>(victim).unwrap().value,
+            Vec2::new(100., 0.)
+        );
+        assert_eq!(
+            world.get::<Velocity>(centered).unwrap().value,
+            Vec2::new(0., 100.)
+        );
+        assert_eq!(world.get::<Velocity>(ally).unwrap().value, Vec2::ZERO);
+        assert_eq!(world.get::<Invulnerable>(victim), Some(&Invulnerable(2)));
+
+        // the victim can't be hit again until its invulnerability runs out
+        let events = tick(&mut app);
+        assert!(hits(&events, victim).is_empty());
+        assert_eq!(hits(&events, centered).len(), 1);
+        assert_eq!(
+            app.world().get::<Invulnerable>(victim),
+            Some(&Invulnerable(1))
+        );
+
+        let events = tick(&mut app);
+        assert_eq!(hits(&events, victim).len(), 1);
+        assert_eq!(
+            app.world().get::<Invulnerable>(victim),
+            Some(&Invulnerable(2))
+        );
+    }
+}
@@ -0,0 +1,435 @@
+use bevy::{input::InputSystem, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContexts, EguiPlugin},
+    egui,
+};
+use std::{fmt::Write, fs};
+
+use crate::{
+    input::{Action, Binding, InputMap, INPUT_MAP_PATH},
+    overlay::DiagnosticsOverlay,
+    physics::{translate_collider, Actor, Area, Contacts, PhysicsSnapshot, Solid, Velocity},
+    player::Player,
+    tiles::{TileDecomposition, TileIndex},
+};
+
+const HELP: &str = "\
+commands:
+  tp <x> <y>       teleport the player
+  gizmos [on|off]  toggle collider gizmos
+  rate <hz>        set the fixed physics rate
+  reload           respawn the level
+  dump             print the physics state
+  rects            compare tile collider counts per decomposition
+  stats            toggle the diagnostics overlay
+  save [file]      snapshot the physics state, to a file if given
+  load [file]      restore the last snapshot, or the one in a file
+  bind <action> <binding>
+                   replace the bindings of an action, e.g. `bind jump KeyZ`
+                   or `bind move KeyAxis(positive: KeyD, negative: KeyA)`
+  unbind <action>  remove every binding of an action
+  savebinds [file] write the bindings to assets/input.ron, or to a file
+  clear            clear the console
+  help             show this message";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsoleCommand {
+    Teleport(Vec2),
+    Gizmos(Option<bool>),
+    Rate(f64),
+    Reload,
+    Dump,
+    Rects,
+    Stats,
+    Save(Option<String>),
+    Load(Option<String>),
+    Bind(Action, Binding),
+    Unbind(Action),
+    SaveBindings(Option<String>),
+    Clear,
+    Help,
+}
+
+impl ConsoleCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut args = line.split_whitespace();
+        let Some(name) = args.next() else {
+            return Err("empty command".into());
+        };
+
+        let mut number = |what: &str| -> Result<f64, String> {
+            let arg = args.next().ok_or_else(|| format!("missing {what}"))?;
+            arg.parse()
+                .map_err(|_| format!("{what} should be a number, got `{arg}`"))
+        };
+
+        match name {
+            "tp" | "teleport" => Ok(Self::Teleport(Vec2::new(
+                number("x")? as f32,
+                number("y")? as f32,
+            ))),
+            "gizmos" => match args.next() {
+                None => Ok(Self::Gizmos(None)),
+                Some("on") => Ok(Self::Gizmos(Some(true))),
+                Some("off") => Ok(Self::Gizmos(Some(false))),
+                Some(arg) => Err(format!("expected `on` or `off`, got `{arg}`")),
+            },
+            "rate" => match number("rate")? {
+                hz if hz > 0. => Ok(Self::Rate(hz)),
+                _ => Err("rate should be positive".into()),
+            },
+            "reload" => Ok(Self::Reload),
+            "dump" => Ok(Self::Dump),
+            "rects" => Ok(Self::Rects),
+            "stats" => Ok(Self::Stats),
+            "save" => Ok(Self::Save(args.next().map(String::from))),
+            "load" => Ok(Self::Load(args.next().map(String::from))),
+            "bind" => {
+                let action = parse_action(args.next())?;
+                let binding = parse_binding(&args.collect::<Vec<_>>().join(" "))?;
+                Ok(Self::Bind(action, binding))
+            }
+            "unbind" => Ok(Self::Unbind(parse_action(args.next())?)),
+            "savebinds" => Ok(Self::SaveBindings(args.next().map(String::from))),
+            "clear" => Ok(Self::Clear),
+            "help" => Ok(Self::Help),
+            _ => Err(format!("unknown command `{name}`, try `help`")),
+        }
+    }
+}
+
+fn parse_action(arg: Option<&str>) -> Result<Action, String> {
+    let arg = arg.ok_or("missing action")?;
+
+    Action::ALL
+        .into_iter()
+        .find(|action| format!("{action:?}").eq_ignore_ascii_case(arg))
+        .ok_or_else(|| format!("unknown action `{arg}`"))
+}
+
+/// Reads a `Binding` written like in `assets/input.ron`, a lone key stands for `Key(..)`
+fn parse_binding(text: &str) -> Result<Binding, String> {
+    if text.is_empty() {
+        return Err("missing binding".into());
+    }
+
+    ron::from_str(text)
+        .or_else(|_| ron::from_str(text).map(Binding::Key))
+        .map_err(|e| format!("could not read binding `{text}`: {e}"))
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct DebugConsole {
+    pub open: bool,
+    pub input: String,
+    pub output: Vec<String>,
+}
+
+impl DebugConsole {
+    pub fn print(&mut self, text: impl Into<String>) {
+        self.output.push(text.into());
+    }
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct ConsoleCommandEvent(pub ConsoleCommand);
+
+pub fn toggle_console(keys: Res<ButtonInput<KeyCode>>, mut console: ResMut<DebugConsole>) {
+    if keys.just_pressed(KeyCode::Backquote) {
+        console.open = !console.open;
+    }
+}
+
+/// Keeps the game from reacting to what is typed into the console
+pub fn block_game_input(console: Res<DebugConsole>, mut keys: ResMut<ButtonInput<KeyCode>>) {
+    if console.open {
+        keys.reset_all();
+    }
+}
+
+pub fn draw_console(
+    mut contexts: EguiContexts,
+    mut console: ResMut<DebugConsole>,
+    mut ev_command: EventWriter<ConsoleCommandEvent>,
+) {
+    if !console.open {
+        return;
+    }
+
+    let console = console.as_mut();
+    // the key that opens the console also arrives as typed text
+    console.input.retain(|c| c != '`');
+
+    egui::Window::new("Console")
+        .default_width(360.)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in &console.output {
+                        ui.monospace(line);
+                    }
+                });
+
+            let response = ui.text_edit_singleline(&mut console.input);
+
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let line = std::mem::take(&mut console.input);
+                console.output.push(format!("> {line}"));
+
+                match ConsoleCommand::parse(&line) {
+                    Ok(command) => {
+                        ev_command.send(ConsoleCommandEvent(command));
+                    }
+                    Err(e) => console.output.push(e),
+                }
+            }
+
+            response.request_focus();
+        });
+}
+
+pub fn run_console_commands(
+    mut commands: Commands,
+    mut ev_command: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<DebugConsole>,
+    mut gizmo_config: ResMut<GizmoConfigStore>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut players: Query<(
+        Entity,
+        &mut Transform,
+        &mut GlobalTransform,
+        &mut Velocity,
+        &Player,
+        Option<&Contacts>,
+    )>,
+    worlds: Query<Entity, With<Handle<LdtkProject>>>,
+    actors: Query<
+        (
+            Entity,
+            Option<&Name>,
+            &Transform,
+            &Velocity,
+            Option<&Contacts>,
+        ),
+        (With<Actor>, Without<Player>),
+    >,
+    solids: Query<(), With<Solid>>,
+    areas: Query<(), With<Area>>,
+    tile_index: Res<TileIndex>,
+    decomposition: Res<TileDecomposition>,
+    mut overlay: ResMut<DiagnosticsOverlay>,
+    mut input_map: ResMut<InputMap>,
+) {
+    for ConsoleCommandEvent(command) in ev_command.read() {
+        match *command {
+            ConsoleCommand::Teleport(position) => {
+                for (_, mut transform, mut global_transform, mut velocity, ..) in &mut players {
+                    let delta = position - global_transform.translation().xy();
+                    translate_collider(&mut transform, &mut global_transform, delta);
+                    velocity.reset_x();
+                    velocity.reset_y();
+                }
+                console.print(format!("teleported to {position}"));
+            }
+            ConsoleCommand::Gizmos(enabled) => {
+                let (config, _) = gizmo_config.config_mut::<DefaultGizmoConfigGroup>();
+                config.enabled = enabled.unwrap_or(!config.enabled);
+                console.print(format!(
+                    "gizmos {}",
+                    if config.enabled { "on" } else { "off" }
+                ));
+            }
+            ConsoleCommand::Rate(hz) => {
+                fixed_time.set_timestep_hz(hz);
+                console.print(format!("physics running at {hz}hz"));
+            }
+            ConsoleCommand::Reload => {
+                for world in &worlds {
+                    commands.entity(world).insert(Respawn);
+                }
+                console.print("reloading level");
+            }
+            ConsoleCommand::Dump => {
+                let mut dump = format!(
+                    "{} solids, {} areas, {}hz",
+                    solids.iter().len(),
+                    areas.iter().len(),
+                    fixed_time.timestep().as_secs_f64().recip()
+                );
+
+                for (entity, transform, _, velocity, player, contacts) in &players {
+                    let _ = write!(
+                        dump,
+                        "\nplayer {entity}: pos {} vel {} rem {} contacts {:?} grounded {} climbing {}",
+                        transform.translation.xy(),
+                        velocity.value,
+                        velocity.remainder,
+                        contacts.map(Contacts::sides).unwrap_or_default(),
+                        player.grounded,
+                        player.climbing
+                    );
+                }
+
+                for (entity, name, transform, velocity, contacts) in &actors {
+                    let _ = write!(
+                        dump,
+                        "\n{} {entity}: pos {} vel {} rem {} contacts {:?}",
+                        name.map_or("actor", |name| name.as_str()),
+                        transform.translation.xy(),
+                        velocity.value,
+                        velocity.remainder,
+                        contacts.map(Contacts::sides).unwrap_or_default()
+                    );
+                }
+
+                info!("{dump}");
+                console.output.extend(dump.lines().map(String::from));
+            }
+            ConsoleCommand::Rects => {
+                for candidate in TileDecomposition::ALL {
+                    let count: usize = tile_index
+                        .levels
+                        .values()
+                        .flat_map(|level| {
+                            level.cells.iter().map(|(&kind, cells)| {
+                                candidate
+                                    .decompose(kind, cells, cells, level.width, level.height)
+                                    .len()
+                            })
+                        })
+                        .sum();
+
+                    let current = if candidate == *decomposition {
+                        " (current)"
+                    } else {
+                        ""
+                    };
+
+                    console.print(format!("{candidate:?}: {count}{current}"));
+                }
+            }
+            ConsoleCommand::Stats => overlay.visible = !overlay.visible,
+            ConsoleCommand::Save(ref path) => {
+                let path = path.clone();
+                commands.add(move |world: &mut World| {
+                    let message = match PhysicsSnapshot::capture(world) {
+                        Ok(snapshot) => {
+                            let message = match &path {
+                                Some(path) => match fs::write(path, snapshot.to_bytes()) {
+                                    Ok(()) => format!("saved {} bytes to {path}", snapshot.len()),
+                                    Err(e) => format!("could not write {path}: {e}"),
+                                },
+                                None => format!("saved {} bytes", snapshot.len()),
+                            };
+                            world.insert_resource(snapshot);
+                            message
+                        }
+                        Err(e) => format!("could not save: {e}"),
+                    };
+                    world.resource_mut::<DebugConsole>().print(message);
+                });
+            }
+            ConsoleCommand::Load(ref path) => {
+                let path = path.clone();
+                commands.add(move |world: &mut World| {
+                    let snapshot = match &path {
+                        Some(path) => fs::read(path)
+                            .map(PhysicsSnapshot::from_bytes)
+                            .map_err(|e| format!("could not read {path}: {e}")),
+                        None => world
+                            .remove_resource::<PhysicsSnapshot>()
+                            .ok_or_else(|| "nothing saved yet".to_string()),
+                    };
+
+                    let message = match snapshot {
+                        Ok(snapshot) => {
+                            let result = snapshot.restore(world);
+                            // kept around so the same state can be loaded again
+                            world.insert_resource(snapshot);
+
+                            match result {
+                                Ok(()) => "loaded snapshot".into(),
+                                Err(e) => format!("could not load: {e}"),
+                            }
+                        }
+                        Err(message) => message,
+                    };
+                    world.resource_mut::<DebugConsole>().print(message);
+                });
+            }
+            ConsoleCommand::Bind(action, binding) => {
+                input_map.rebind(action, vec![binding]);
+                console.print(format!("{action:?} bound to {binding:?}"));
+            }
+            ConsoleCommand::Unbind(action) => {
+                input_map.unbind(action);
+                console.print(format!("{action:?} unbound"));
+            }
+            ConsoleCommand::SaveBindings(ref path) => {
+                let path = path.as_deref().unwrap_or(INPUT_MAP_PATH);
+
+                match input_map.save(path) {
+                    Ok(()) => console.print(format!("saved bindings to {path}")),
+                    Err(e) => console.print(format!("could not write {path}: {e}")),
+                }
+            }
+            ConsoleCommand::Clear => console.output.clear(),
+            ConsoleCommand::Help => console.output.extend(HELP.lines().map(String::from)),
+        }
+    }
+}
+
+pub struct DebugConsolePlugin;
+
+impl Plugin for DebugConsolePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+
+        app.init_resource::<DebugConsole>()
+            .add_event::<ConsoleCommandEvent>()
+            .add_systems(
+                PreUpdate,
+                (toggle_console, block_game_input)
+                    .chain()
+                    .after(InputSystem),
+            )
+            .add_systems(Update, (draw_console, run_console_commands).chain());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bindings() {
+        assert_eq!(
+            ConsoleCommand::parse("bind jump KeyZ"),
+            Ok(ConsoleCommand::Bind(
+                Action::Jump,
+                Binding::Key(KeyCode::KeyZ)
+            ))
+        );
+        assert_eq!(
+            ConsoleCommand::parse("bind Move KeyAxis(positive: KeyD, negative: KeyA)"),
+            Ok(ConsoleCommand::Bind(
+                Action::Move,
+                Binding::KeyAxis {
+                    positive: KeyCode::KeyD,
+                    negative: KeyCode::KeyA,
+                }
+            ))
+        );
+        assert_eq!(
+            ConsoleCommand::parse("unbind dash"),
+            Ok(ConsoleCommand::Unbind(Action::Dash))
+        );
+        assert!(ConsoleCommand::parse("bind fly KeyZ").is_err());
+        assert!(ConsoleCommand::parse("bind jump").is_err());
+    }
+}
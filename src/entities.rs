@@ -0,0 +1,617 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{ldtk::TilesetDefinition, prelude::*};
+
+use crate::{
+    camera::CameraTarget,
+    combat::{apply_knockback, Hitbox, Hurtbox, Team},
+    levels::level_ready,
+    physics::*,
+    player::{approach, Player, PlayerBundle},
+    rewind::rewinding,
+    tiles::{Climbable, Hazard},
+};
+
+const ENEMY_SPEED: f32 = 40.;
+const ENEMY_GRAVITY: f32 = 1000.;
+const ENEMY_FALL_VELOCITY: f32 = -400.;
+const ENEMY_KNOCKBACK: f32 = 200.;
+const PLATFORM_SPEED: f32 = 40.;
+
+/// Half the size of an LDtk entity, colliders are sized to match what was placed in the editor
+fn half_size(entity_instance: &EntityInstance) -> Vec2 {
+    IVec2::new(entity_instance.width, entity_instance.height).as_vec2() / 2.
+}
+
+/// Snapshots and rollback tell LDtk entities apart by their iid, which is the same every run
+fn stable_id(entity_instance: &EntityInstance) -> StableId {
+    StableId::from_name(&entity_instance.iid)
+}
+
+/// Where the player appears when a level with this entity is loaded
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct PlayerSpawn;
+
+#[derive(Clone, Debug, Default, Bundle, LdtkEntity)]
+pub struct PlayerSpawnBundle {
+    spawn: PlayerSpawn,
+    #[with(stable_id)]
+    stable_id: StableId,
+}
+
+/// Where the player goes back to after touching a hazard
+#[derive(Copy, Clone, PartialEq, Debug, Default, Component, Reflect)]
+#[reflect(Component)]
+pub struct RespawnPoint(pub Vec2);
+
+/// Walks back and forth, turning around at walls
+#[derive(Copy, Clone, PartialEq, Debug, Component, Reflect)]
+#[reflect(Component)]
+pub struct Enemy {
+    pub speed: f32,
+    pub direction: f32,
+}
+
+impl Default for Enemy {
+    fn default() -> Self {
+        Self {
+            speed: ENEMY_SPEED,
+            direction: 1.,
+        }
+    }
+}
+
+impl From<&EntityInstance> for Enemy {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Self {
+            speed: entity_instance
+                .get_float_field("speed")
+                .copied()
+                .unwrap_or(ENEMY_SPEED),
+            ..default()
+        }
+    }
+}
+
+fn enemy_actor(entity_instance: &EntityInstance) -> ActorBundle {
+    ActorBundle::new(Vec2::ZERO, Collider::aabb(half_size(entity_instance)))
+}
+
+fn enemy_hitbox(entity_instance: &EntityInstance) -> Hitbox {
+    Hitbox::new(
+        SharedShape::aabb(half_size(entity_instance)),
+        ENEMY_KNOCKBACK,
+    )
+}
+
+fn enemy_team(_: &EntityInstance) -> Team {
+    Team::Enemy
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct EnemyBundle {
+    #[from_entity_instance]
+    enemy: Enemy,
+    #[with(enemy_actor)]
+    actor: ActorBundle,
+    #[with(enemy_hitbox)]
+    hitbox: Hitbox,
+    #[with(enemy_team)]
+    team: Team,
+    #[with(stable_id)]
+    stable_id: StableId,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+/// A solid that travels along a path, carrying the actors standing on it
+///
+/// The path is stored relative to where the platform was placed and is looped through in order
+#[derive(Clone, PartialEq, Debug, Default, Component, Reflect)]
+#[reflect(Component)]
+pub struct MovingPlatform {
+    pub path: Vec<Vec2>,
+    pub speed: f32,
+    /// Index of the path point the platform is heading to
+    pub target: usize,
+    /// How far the platform has moved from where it was placed
+    pub offset: Vec2,
+}
+
+#[derive(Bundle)]
+pub struct MovingPlatformBundle {
+    platform: MovingPlatform,
+    velocity: Velocity,
+    solid: SolidBundle,
+    stable_id: StableId,
+    entity_instance: EntityInstance,
+}
+
+impl LdtkEntity for MovingPlatformBundle {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _: Option<&Handle<Image>>,
+        _: Option<&TilesetDefinition>,
+        _: &AssetServer,
+        _: &mut Assets<TextureAtlasLayout>,
+    ) -> Self {
+        let grid_size = layer_instance.grid_size as f32;
+
+        // LDtk points are grid cells with y pointing down, they mark where the
+        // platform's own cell moves to
+        let path = std::iter::once(Vec2::ZERO)
+            .chain(
+                entity_instance
+                    .iter_points_field("path")
+                    .into_iter()
+                    .flatten()
+                    .map(|point| {
+                        let cells = *point - entity_instance.grid;
+                        Vec2::new(cells.x as f32, -cells.y as f32) * grid_size
+                    }),
+            )
+            .collect();
+
+        Self {
+            platform: MovingPlatform {
+                path,
+                speed: entity_instance
+                    .get_float_field("speed")
+                    .copied()
+                    .unwrap_or(PLATFORM_SPEED),
+                target: 0,
+                offset: Vec2::ZERO,
+            },
+            velocity: Velocity::default(),
+            solid: SolidBundle::new(Vec2::ZERO, half_size(entity_instance)),
+            stable_id: stable_id(entity_instance),
+            entity_instance: entity_instance.clone(),
+        }
+    }
+}
+
+/// Sent when an actor starts overlapping a trigger
+#[derive(Event, Debug, Clone)]
+#[allow(
+    dead_code,
+    reason = "read by game code reacting to triggers, the demo has none yet"
+)]
+pub struct TriggerEvent {
+    pub trigger: Entity,
+    pub actor: Entity,
+    pub id: String,
+}
+
+/// An area that sends a `TriggerEvent` for every actor entering it
+#[derive(Clone, PartialEq, Debug, Default, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct Trigger {
+    #[reflect(ignore)]
+    pub id: String,
+    /// Actors inside the trigger as of the last tick, sorted
+    pub inside: Vec<StableId>,
+}
+
+impl From<&EntityInstance> for Trigger {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Self {
+            id: entity_instance
+                .get_string_field("id")
+                .cloned()
+                .unwrap_or_else(|_| entity_instance.iid.clone()),
+            ..default()
+        }
+    }
+}
+
+fn entity_area(entity_instance: &EntityInstance) -> AreaBundle {
+    AreaBundle::new(Vec2::ZERO, half_size(entity_instance))
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct TriggerBundle {
+    #[from_entity_instance]
+    trigger: Trigger,
+    #[with(entity_area)]
+    area: AreaBundle,
+    #[with(stable_id)]
+    stable_id: StableId,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+/// Touching it moves the player's `RespawnPoint` here
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Checkpoint;
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct CheckpointBundle {
+    checkpoint: Checkpoint,
+    #[with(entity_area)]
+    area: AreaBundle,
+    #[with(stable_id)]
+    stable_id: StableId,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+/// A ladder placed as an entity instead of painted on an IntGrid layer
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct ClimbableBundle {
+    climbable: Climbable,
+    #[with(entity_area)]
+    area: AreaBundle,
+    #[with(stable_id)]
+    stable_id: StableId,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+/// Gives climbable entities the one-way floor along their top row that painted ladders get
+pub fn spawn_climbable_tops(
+    mut commands: Commands,
+    climbables: Query<(Entity, &EntityInstance, &Parent), Added<Climbable>>,
+    layers: Query<&LayerMetadata>,
+) {
+    for (entity, entity_instance, layer) in &climbables {
+        let half_size = half_size(entity_instance);
+        let row_height = layers
+            .get(layer.get())
+            .map_or(half_size.y * 2., |layer| layer.grid_size as f32)
+            .min(half_size.y * 2.);
+        let top_half_size = Vec2::new(half_size.x, row_height / 2.);
+
+        commands.entity(entity).with_children(|climbable| {
+            climbable.spawn((
+                Name::new("ClimbableTop"),
+                OneWay,
+                SolidBundle::new(Vec2::new(0., half_size.y - top_half_size.y), top_half_size),
+            ));
+        });
+    }
+}
+
+/// Inserts the physics components requested through custom fields
+///
+/// - `one_way: Bool` makes a solid `OneWay`
+/// - `coyote_time: Float` gives an actor `CoyoteTime`
+/// - `ceiling_correction: Int` and `ledge_correction: Int` give an actor `CornerCorrection`
+pub fn apply_entity_fields(
+    mut commands: Commands,
+    entities: Query<(Entity, &EntityInstance), Added<EntityInstance>>,
+) {
+    for (entity, entity_instance) in &entities {
+        let mut entity = commands.entity(entity);
+
+        if entity_instance.get_bool_field("one_way") == Ok(&true) {
+            entity.insert(OneWay);
+        }
+
+        if let Ok(&duration) = entity_instance.get_float_field("coyote_time") {
+            entity.insert(CoyoteTime::new(duration));
+        }
+
+        let ceiling = entity_instance.get_int_field("ceiling_correction").ok();
+        let ledge = entity_instance.get_int_field("ledge_correction").ok();
+
+        if ceiling.is_some() || ledge.is_some() {
+            let default = CornerCorrection::default();
+
+            entity.insert(CornerCorrection {
+                ceiling: ceiling.copied().unwrap_or(default.ceiling),
+                ledge: ledge.copied().unwrap_or(default.ledge),
+            });
+        }
+    }
+}
+
+/// Spawns the player at a spawn point, unless there already is one
+///
+/// The player isn't part of any level so it survives walking from one level into the next.
+/// Spawn points in the selected level win over the ones in its neighbours
+pub fn spawn_player(
+    mut commands: Commands,
+    spawns: Query<(&Transform, &Parent), Added<PlayerSpawn>>,
+    layers: Query<(&Transform, &Parent), Without<PlayerSpawn>>,
+    levels: Query<(&Transform, &LevelIid, &Parent)>,
+    worlds: Query<&Handle<LdtkProject>>,
+    players: Query<(), With<Player>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    level_selection: Res<LevelSelection>,
+    asset_server: Res<AssetServer>,
+) {
+    if !players.is_empty() {
+        return;
+    }
+
+    let spawn_points = spawns.iter().filter_map(|(spawn_transform, layer)| {
+        let (layer_transform, level) = layers.get(layer.get()).ok()?;
+        let (level_transform, level_iid, world) = levels.get(level.get()).ok()?;
+
+        let selected = worlds
+            .get(world.get())
+            .ok()
+            .and_then(|handle| ldtk_project_assets.get(handle))
+            .and_then(|ldtk_project| {
+                ldtk_project.find_raw_level_by_level_selection(&level_selection)
+            })
+            .is_some_and(|level| level.iid == level_iid.get().as_str());
+
+        let position = level_transform.translation.xy()
+            + layer_transform.translation.xy()
+            + spawn_transform.translation.xy();
+
+        Some((selected, position))
+    });
+
+    let Some((_, position)) = spawn_points.max_by_key(|(selected, _)| *selected) else {
+        return;
+    };
+
+    commands.spawn((
+        Name::new("Player"),
+        PlayerBundle::new(asset_server.load("player.png"), position),
+        RespawnPoint(position),
+        CameraTarget,
+    ));
+}
+
+pub fn patrol_enemies(
+    time: Res<Time<Fixed>>,
+    mut enemies: Query<(&mut Enemy, &mut Velocity, Option<&Contacts>)>,
+) {
+    let delta = time.timestep().as_secs_f32();
+
+    for (mut enemy, mut velocity, contacts) in &mut enemies {
+        // walls hit during the last tick, which are part of the snapshot unlike its events
+        if let Some(contacts) = contacts {
+            if contacts.east {
+                enemy.direction = -1.;
+            } else if contacts.west {
+                enemy.direction = 1.;
+            }
+        }
+
+        velocity.value.x = enemy.speed * enemy.direction;
+        velocity.value.y = approach(velocity.value.y, ENEMY_FALL_VELOCITY, ENEMY_GRAVITY * delta);
+    }
+}
+
+/// Moves platforms towards their next path point in whole pixels
+///
+/// Actors standing on a platform move along with it, actors in its way are not pushed
+pub fn move_platforms(
+    time: Res<Time<Fixed>>,
+    mut platforms: Query<
+        (
+            Option<&StableId>,
+            &mut MovingPlatform,
+            &mut Velocity,
+            &mut Transform,
+            &mut GlobalTransform,
+            &Collider,
+            Option<&ColliderOffset>,
+        ),
+        Without<Actor>,
+    >,
+    mut actors: Query<
+        (
+            &Collider,
+            &mut Transform,
+            &mut GlobalTransform,
+            Option<&ColliderOffset>,
+        ),
+        With<Actor>,
+    >,
+) {
+    let delta = time.timestep().as_secs_f32();
+
+    let mut platforms = platforms.iter_mut().collect::<Vec<_>>();
+    platforms.sort_by_key(|(id, ..)| id.copied());
+
+    for (_, mut platform, mut velocity, mut transform, mut global_transform, collider, offset) in
+        platforms
+    {
+        if platform.path.len() < 2 {
+            continue;
+        }
+
+        let remaining = platform.path[platform.target] - platform.offset;
+
+        if remaining == Vec2::ZERO {
+            platform.target = (platform.target + 1) % platform.path.len();
+            velocity.remainder = Vec2::ZERO;
+            continue;
+        }
+
+        velocity.value = remaining.normalize() * platform.speed;
+
+        let step = velocity.step(delta).as_vec2();
+        // never overshoot the path point
+        let step = step.clamp(-remaining.abs(), remaining.abs());
+
+        if step == Vec2::ZERO {
+            continue;
+        }
+
+        let TypedShape::Aabb(shape) = collider.as_typed_shape() else {
+            continue;
+        };
+
+        let solid = shape.aabb(collider_position(&global_transform, offset));
+
+        for (actor, mut actor_transform, mut actor_global_transform, actor_offset) in &mut actors {
+            let position = collider_position(&actor_global_transform, actor_offset);
+
+            let riding =
+                !actor.collides(position, &solid) && actor.collides(position + Vec2::NEG_Y, &solid);
+
+            if riding {
+                translate_collider(&mut actor_transform, &mut actor_global_transform, step);
+            }
+        }
+
+        translate_collider(&mut transform, &mut global_transform, step);
+        platform.offset += step;
+    }
+}
+
+pub fn detect_triggers(
+    mut ev_trigger: EventWriter<TriggerEvent>,
+    mut triggers: Query<(
+        Entity,
+        Option<&StableId>,
+        &mut Trigger,
+        &Collider,
+        &GlobalTransform,
+        Option<&ColliderOffset>,
+    )>,
+    actors: Query<
+        (
+            Entity,
+            &StableId,
+            &Collider,
+            &GlobalTransform,
+            Option<&ColliderOffset>,
+        ),
+        With<Actor>,
+    >,
+) {
+    // sorted so events are sent in the same order on every peer
+    let mut triggers = triggers.iter_mut().collect::<Vec<_>>();
+    triggers.sort_by_key(|(_, id, ..)| id.copied());
+
+    let mut actors = actors.iter().collect::<Vec<_>>();
+    actors.sort_by_key(|(_, id, ..)| **id);
+
+    for (trigger_entity, _, mut trigger, collider, transform, offset) in triggers {
+        let TypedShape::Aabb(shape) = collider.as_typed_shape() else {
+            continue;
+        };
+
+        let area = shape.aabb(collider_position(transform, offset));
+
+        let inside = actors
+            .iter()
+            .filter(|(_, _, actor, actor_transform, actor_offset)| {
+                actor.collides(collider_position(actor_transform, *actor_offset), &area)
+            })
+            .map(|&(entity, &id, ..)| (entity, id))
+            .collect::<Vec<_>>();
+
+        for &(actor, id) in &inside {
+            if trigger.inside.binary_search(&id).is_err() {
+                ev_trigger.send(TriggerEvent {
+                    trigger: trigger_entity,
+                    actor,
+                    id: trigger.id.clone(),
+                });
+            }
+        }
+
+        trigger.inside = inside.into_iter().map(|(_, id)| id).collect();
+    }
+}
+
+pub fn activate_checkpoints(
+    mut players: Query<
+        (
+            &Collider,
+            &GlobalTransform,
+            Option<&ColliderOffset>,
+            &mut RespawnPoint,
+        ),
+        With<Player>,
+    >,
+    checkpoints: Query<(&Collider, &GlobalTransform, Option<&ColliderOffset>), With<Checkpoint>>,
+) {
+    for (collider, transform, offset, mut respawn_point) in &mut players {
+        let position = collider_position(transform, offset);
+
+        let checkpoint = checkpoints
+            .iter()
+            .find(|&checkpoint| overlaps_any(collider, position, std::iter::once(checkpoint)));
+
+        if let Some((_, checkpoint_transform, _)) = checkpoint {
+            respawn_point.0 = checkpoint_transform.translation().xy();
+        }
+    }
+}
+
+/// Sends players touching a hazard back to their respawn point
+///
+/// Tested here rather than through `DamageEvent`s, which invulnerable players don't get
+pub fn respawn_on_hazard(
+    mut players: Query<
+        (
+            &Hurtbox,
+            &mut Transform,
+            &mut GlobalTransform,
+            &mut Velocity,
+            &RespawnPoint,
+        ),
+        With<Player>,
+    >,
+    hazards: Query<(&Hitbox, &GlobalTransform), (With<Hazard>, Without<Player>)>,
+) {
+    for (hurtbox, mut transform, mut global_transform, mut velocity, respawn_point) in &mut players
+    {
+        let position = global_transform.translation().xy();
+
+        let touching = hazards.iter().any(|(hitbox, hazard_transform)| {
+            hitbox.overlaps(hazard_transform.translation().xy(), hurtbox, position)
+        });
+
+        if !touching {
+            continue;
+        }
+
+        let delta = respawn_point.0 - global_transform.translation().xy();
+        translate_collider(&mut transform, &mut global_transform, delta);
+        velocity.reset_x();
+        velocity.reset_y();
+    }
+}
+
+pub struct LdtkEntitiesPlugin;
+
+impl Plugin for LdtkEntitiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_ldtk_entity::<PlayerSpawnBundle>("Spawn")
+            .register_ldtk_entity::<PlayerSpawnBundle>("Player")
+            .register_ldtk_entity::<EnemyBundle>("Enemy")
+            .register_ldtk_entity::<MovingPlatformBundle>("MovingPlatform")
+            .register_ldtk_entity::<TriggerBundle>("Trigger")
+            .register_ldtk_entity::<CheckpointBundle>("Checkpoint")
+            .register_ldtk_entity::<ClimbableBundle>("Climbable")
+            .register_ldtk_entity::<ClimbableBundle>("Ladder")
+            .snapshot_component::<RespawnPoint>()
+            .snapshot_component::<Enemy>()
+            .snapshot_component::<MovingPlatform>()
+            .snapshot_component::<Trigger>()
+            .add_event::<TriggerEvent>()
+            .add_systems(
+                Update,
+                (apply_entity_fields, spawn_climbable_tops, spawn_player),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    patrol_enemies
+                        .before(Physics::Simulation)
+                        .run_if(level_ready.and_then(not(rewinding))),
+                    move_platforms
+                        .in_set(Physics::Simulation)
+                        .before(simulate_actor_movement),
+                    (
+                        detect_triggers,
+                        activate_checkpoints,
+                        // a respawn overrides the knockback of the same hit
+                        respawn_on_hazard.after(apply_knockback),
+                    )
+                        .after(Physics::Simulation)
+                        .run_if(level_ready.and_then(not(rewinding))),
+                ),
+            );
+    }
+}
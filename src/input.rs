@@ -0,0 +1,337 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io, path::Path};
+
+pub const INPUT_MAP_PATH: &str = "assets/input.ron";
+
+const DEADZONE: f32 = 0.25;
+
+/// Gameplay actions the player systems react to
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Reflect, Serialize, Deserialize,
+)]
+pub enum Action {
+    Move,
+    Jump,
+    Dash,
+    Up,
+    Down,
+    Rewind,
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [
+        Action::Move,
+        Action::Jump,
+        Action::Dash,
+        Action::Up,
+        Action::Down,
+        Action::Rewind,
+    ];
+}
+
+/// A physical input that drives an action
+///
+/// Every binding resolves to a value in `-1..=1`, buttons resolve to `0` or `1`
+#[derive(Debug, Copy, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    KeyAxis {
+        positive: KeyCode,
+        negative: KeyCode,
+    },
+    GamepadButton(GamepadButtonType),
+    GamepadButtonAxis {
+        positive: GamepadButtonType,
+        negative: GamepadButtonType,
+    },
+    GamepadAxis(GamepadAxisType),
+    /// Only the positive (or negative) half of a stick axis, e.g. stick down for `Action::Down`
+    GamepadAxisDirection {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+fn pressed<T: Copy + Eq + std::hash::Hash + Send + Sync>(input: &ButtonInput<T>, button: T) -> f32 {
+    if input.pressed(button) {
+        1.
+    } else {
+        0.
+    }
+}
+
+impl Binding {
+    pub fn value(&self, devices: &InputDevices, deadzone: f32) -> f32 {
+        let keys = devices.keys;
+
+        match *self {
+            Binding::Key(key) => pressed(keys, key),
+            Binding::KeyAxis { positive, negative } => {
+                pressed(keys, positive) - pressed(keys, negative)
+            }
+            Binding::GamepadButton(button) => devices.gamepad_button(button),
+            Binding::GamepadButtonAxis { positive, negative } => {
+                devices.gamepad_button(positive) - devices.gamepad_button(negative)
+            }
+            Binding::GamepadAxis(axis) => apply_deadzone(devices.gamepad_axis(axis), deadzone),
+            Binding::GamepadAxisDirection { axis, positive } => {
+                let value = apply_deadzone(devices.gamepad_axis(axis), deadzone);
+
+                if positive {
+                    value.max(0.)
+                } else {
+                    (-value).max(0.)
+                }
+            }
+        }
+    }
+}
+
+/// Zeroes values inside the deadzone and rescales the rest back to `0..=1`
+pub fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    if value.abs() <= deadzone {
+        0.
+    } else {
+        value.signum() * (value.abs() - deadzone) / (1. - deadzone)
+    }
+}
+
+fn strongest(acc: f32, value: f32) -> f32 {
+    if value.abs() > acc.abs() {
+        value
+    } else {
+        acc
+    }
+}
+
+/// Read-only view over every device an action can be bound to
+pub struct InputDevices<'a> {
+    pub keys: &'a ButtonInput<KeyCode>,
+    pub gamepads: &'a Gamepads,
+    pub gamepad_buttons: &'a ButtonInput<GamepadButton>,
+    pub gamepad_axes: &'a Axis<GamepadAxis>,
+}
+
+impl InputDevices<'_> {
+    fn gamepad_button(&self, button: GamepadButtonType) -> f32 {
+        self.gamepads
+            .iter()
+            .map(|gamepad| pressed(self.gamepad_buttons, GamepadButton::new(gamepad, button)))
+            .fold(0., f32::max)
+    }
+
+    // the strongest deflection among all connected gamepads wins
+    fn gamepad_axis(&self, axis: GamepadAxisType) -> f32 {
+        self.gamepads
+            .iter()
+            .filter_map(|gamepad| self.gamepad_axes.get(GamepadAxis::new(gamepad, axis)))
+            .fold(0., strongest)
+    }
+}
+
+/// Action bindings, rebindable at runtime and loadable from `assets/input.ron`
+#[derive(Resource, Debug, Clone, Reflect, Serialize, Deserialize)]
+#[reflect(Resource)]
+pub struct InputMap {
+    pub deadzone: f32,
+    pub bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut map = Self {
+            deadzone: DEADZONE,
+            bindings: HashMap::new(),
+        };
+
+        map.bind(
+            Action::Move,
+            Binding::KeyAxis {
+                positive: KeyCode::ArrowRight,
+                negative: KeyCode::ArrowLeft,
+            },
+        )
+        .bind(
+            Action::Move,
+            Binding::GamepadAxis(GamepadAxisType::LeftStickX),
+        )
+        .bind(
+            Action::Move,
+            Binding::GamepadButtonAxis {
+                positive: GamepadButtonType::DPadRight,
+                negative: GamepadButtonType::DPadLeft,
+            },
+        )
+        .bind(Action::Jump, Binding::Key(KeyCode::KeyC))
+        .bind(
+            Action::Jump,
+            Binding::GamepadButton(GamepadButtonType::South),
+        )
+        .bind(Action::Dash, Binding::Key(KeyCode::KeyX))
+        .bind(
+            Action::Dash,
+            Binding::GamepadButton(GamepadButtonType::West),
+        )
+        .bind(Action::Up, Binding::Key(KeyCode::ArrowUp))
+        .bind(
+            Action::Up,
+            Binding::GamepadButton(GamepadButtonType::DPadUp),
+        )
+        .bind(
+            Action::Up,
+            Binding::GamepadAxisDirection {
+                axis: GamepadAxisType::LeftStickY,
+                positive: true,
+            },
+        )
+        .bind(Action::Down, Binding::Key(KeyCode::ArrowDown))
+        .bind(
+            Action::Down,
+            Binding::GamepadButton(GamepadButtonType::DPadDown),
+        )
+        .bind(
+            Action::Down,
+            Binding::GamepadAxisDirection {
+                axis: GamepadAxisType::LeftStickY,
+                positive: false,
+            },
+        )
+        .bind(Action::Rewind, Binding::Key(KeyCode::KeyR))
+        .bind(
+            Action::Rewind,
+            Binding::GamepadButton(GamepadButtonType::LeftTrigger2),
+        );
+
+        map
+    }
+}
+
+impl InputMap {
+    pub fn bind(&mut self, action: Action, binding: Binding) -> &mut Self {
+        self.bindings.entry(action).or_default().push(binding);
+        self
+    }
+
+    pub fn rebind(&mut self, action: Action, bindings: Vec<Binding>) -> &mut Self {
+        self.bindings.insert(action, bindings);
+        self
+    }
+
+    pub fn unbind(&mut self, action: Action) -> &mut Self {
+        self.bindings.remove(&action);
+        self
+    }
+
+    /// Resolves an action to the binding with the largest magnitude
+    pub fn value(&self, action: Action, devices: &InputDevices) -> f32 {
+        self.bindings
+            .get(&action)
+            .into_iter()
+            .flatten()
+            .map(|binding| binding.value(devices, self.deadzone))
+            .fold(0., strongest)
+            .clamp(-1., 1.)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        ron::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let contents = ron::ser::to_string_pretty(self, default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, contents)
+    }
+}
+
+/// Current value of every action, refreshed from the `InputMap` once per fixed tick
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ActionState {
+    values: HashMap<Action, f32>,
+    buttons: ButtonInput<Action>,
+}
+
+impl ActionState {
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.buttons.pressed(action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.buttons.just_pressed(action)
+    }
+
+    /// Sets the value of an action, pressing it when non-zero
+    ///
+    /// `just_pressed` is derived from the previous call, so it stays accurate
+    /// no matter where the state is fed from
+    pub fn set(&mut self, action: Action, value: f32) {
+        self.values.insert(action, value);
+
+        if value != 0. {
+            self.buttons.press(action);
+        } else {
+            self.buttons.release(action);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.buttons.clear();
+    }
+}
+
+pub fn load_input_map(mut commands: Commands) {
+    let input_map = match InputMap::load(INPUT_MAP_PATH) {
+        Ok(input_map) => input_map,
+        Err(e) => {
+            warn!("could not load {INPUT_MAP_PATH}, using default bindings: {e}");
+            InputMap::default()
+        }
+    };
+
+    commands.insert_resource(input_map);
+}
+
+pub fn update_action_state(
+    mut action_state: ResMut<ActionState>,
+    input_map: Res<InputMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+) {
+    let devices = InputDevices {
+        keys: &keys,
+        gamepads: &gamepads,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad_axes: &gamepad_axes,
+    };
+
+    action_state.clear();
+
+    for action in Action::ALL {
+        action_state.set(action, input_map.value(action, &devices));
+    }
+}
+
+/// Actions are sampled once per fixed tick, systems that overwrite them
+/// (e.g. replays) should run after this set
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
+pub struct ActionSystem;
+
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputMap>()
+            .init_resource::<ActionState>()
+            .register_type::<InputMap>()
+            .add_systems(PreStartup, load_input_map)
+            .add_systems(FixedPreUpdate, update_action_state.in_set(ActionSystem));
+    }
+}
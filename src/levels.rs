@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{ldtk::Level, prelude::*};
+use std::collections::HashSet;
+
+use crate::{
+    entities::{PlayerSpawn, RespawnPoint},
+    physics::*,
+    player::Player,
+};
+
+/// Tracks which levels are fully spawned and which one the player is in
+#[derive(Resource, Debug, Default)]
+pub struct LevelStreaming {
+    /// The level containing the player
+    pub current: Option<LevelIid>,
+    /// Levels whose tiles, entities and colliders have all spawned
+    pub loaded: HashSet<LevelIid>,
+}
+
+impl LevelStreaming {
+    /// Whether the level the player is in can be simulated
+    pub fn is_ready(&self) -> bool {
+        self.current
+            .as_ref()
+            .is_none_or(|current| self.loaded.contains(current))
+    }
+}
+
+/// The world space rectangle covered by a level, as placed by `LevelSpawnBehavior::UseWorldTranslation`
+pub fn level_bounds(level: &Level) -> Rect {
+    let min = Vec2::new(level.world_x as f32, -(level.world_y + level.px_hei) as f32);
+    let size = Vec2::new(level.px_wid as f32, level.px_hei as f32);
+
+    Rect::from_corners(min, min + size)
+}
+
+/// Run condition that holds the simulation back until the player's level has its colliders,
+/// so the player doesn't fall through the floor of a level that is still spawning
+pub fn level_ready(streaming: Res<LevelStreaming>) -> bool {
+    streaming.is_ready()
+}
+
+pub fn track_loaded_levels(
+    mut ev_level: EventReader<LevelEvent>,
+    mut streaming: ResMut<LevelStreaming>,
+) {
+    for ev in ev_level.read() {
+        match ev {
+            // transformed is sent the frame after spawning, after the colliders
+            // of the level's tiles have been built
+            LevelEvent::Transformed(iid) => {
+                streaming.loaded.insert(iid.clone());
+            }
+            LevelEvent::SpawnTriggered(iid) | LevelEvent::Despawned(iid) => {
+                streaming.loaded.remove(iid);
+            }
+            LevelEvent::Spawned(_) => (),
+        }
+    }
+}
+
+/// Selects the level the player is in, which loads its neighbours and unloads the rest
+pub fn follow_player_level(
+    players: Query<&Transform, With<Player>>,
+    worlds: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut streaming: ResMut<LevelStreaming>,
+) {
+    let Ok(transform) = players.get_single() else {
+        return;
+    };
+
+    let position = transform.translation.xy();
+
+    let Some(level) = worlds
+        .iter()
+        .filter_map(|handle| ldtk_project_assets.get(handle))
+        .flat_map(|ldtk_project| ldtk_project.iter_raw_levels())
+        .find(|level| level_bounds(level).contains(position))
+    else {
+        // between levels or outside of the world, keep whatever is loaded
+        return;
+    };
+
+    let iid = LevelIid::new(level.iid.clone());
+
+    // selecting the same level again would still count as a change
+    level_selection.set_if_neq(LevelSelection::Iid(iid.clone()));
+    streaming.current = Some(iid);
+}
+
+/// Moves the player to the nearest spawn point when the level they're in respawned with a solid
+/// where they stand, e.g. after `level.ldtk` was edited and hot reloaded
+pub fn rescue_player_from_solids(
+    mut ev_level: EventReader<LevelEvent>,
+    streaming: Res<LevelStreaming>,
+    mut players: Query<
+        (
+            &Collider,
+            &mut Transform,
+            &mut GlobalTransform,
+            Option<&ColliderOffset>,
+            &mut Velocity,
+            Option<&mut RespawnPoint>,
+        ),
+        With<Player>,
+    >,
+    solids: Query<
+        (&Collider, &GlobalTransform, Option<&ColliderOffset>),
+        (With<Solid>, Without<OneWay>, Without<Player>),
+    >,
+    spawns: Query<&GlobalTransform, (With<PlayerSpawn>, Without<Player>)>,
+) {
+    let reloaded = ev_level.read().any(
+        |ev| matches!(ev, LevelEvent::Transformed(iid) if streaming.current.as_ref() == Some(iid)),
+    );
+
+    if !reloaded {
+        return;
+    }
+
+    for (collider, mut transform, mut global_transform, offset, mut velocity, respawn_point) in
+        &mut players
+    {
+        let position = global_transform.translation().xy();
+
+        if !overlaps_any(
+            collider,
+            collider_position(&global_transform, offset),
+            &solids,
+        ) {
+            continue;
+        }
+
+        let nearest = spawns
+            .iter()
+            .map(|spawn| spawn.translation().xy())
+            .min_by(|a, b| {
+                a.distance_squared(position)
+                    .total_cmp(&b.distance_squared(position))
+            });
+
+        let Some(spawn) = nearest else {
+            warn!("the player is stuck in a solid and there is no spawn point to move them to");
+            continue;
+        };
+
+        info!("the player ended up inside a solid, moving them to the spawn point at {spawn}");
+
+        translate_collider(&mut transform, &mut global_transform, spawn - position);
+        velocity.reset_x();
+        velocity.reset_y();
+
+        if let Some(mut respawn_point) = respawn_point {
+            respawn_point.0 = spawn;
+        }
+    }
+}
+
+pub struct LevelStreamingPlugin;
+
+impl Plugin for LevelStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LdtkSettings {
+            // levels are placed where they are in the LDtk world, so the player can walk
+            // from one into the next, which is already spawned along with its colliders
+            level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation {
+                load_level_neighbors: true,
+            },
+            ..default()
+        })
+        .init_resource::<LevelStreaming>()
+        .configure_sets(FixedUpdate, Physics::Simulation.run_if(level_ready))
+        .add_systems(
+            Update,
+            (
+                rescue_player_from_solids,
+                track_loaded_levels,
+                follow_player_level,
+            )
+                .chain(),
+        );
+    }
+}
@@ -1,36 +1,70 @@
 use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, prelude::*};
 use bevy_ecs_ldtk::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
+use physics::SnapshotAppExt;
 
+mod camera;
+mod combat;
+mod console;
+mod entities;
+mod input;
+mod levels;
+mod overlay;
 mod physics;
+mod pixel_perfect;
 mod player;
+mod replay;
+mod rewind;
+mod rollback;
 mod systems;
+mod tiles;
 
 fn main() {
     App::new()
         .insert_resource(LevelSelection::index(0))
         .insert_resource(Time::<Fixed>::from_hz(120.0))
-        .register_ldtk_int_cell::<systems::TileBundle>(1)
-        .register_type::<player::Player>()
+        .register_default_ldtk_int_cell::<systems::TileBundle>()
+        .init_resource::<tiles::IntGridColliders>()
+        .init_resource::<tiles::TileIndex>()
+        .init_resource::<tiles::TileDecomposition>()
+        .register_type::<tiles::TileDecomposition>()
+        .register_type::<tiles::IntGridColliders>()
+        .snapshot_component::<player::Player>()
         .add_plugins((
             DefaultPlugins.set(ImagePlugin::default_nearest()),
             WorldInspectorPlugin::new(),
             LdtkPlugin,
             FrameTimeDiagnosticsPlugin,
+            camera::CameraFollowPlugin,
+            pixel_perfect::PixelPerfectPlugin,
+            console::DebugConsolePlugin,
+            overlay::DiagnosticsOverlayPlugin,
+            entities::LdtkEntitiesPlugin,
+            levels::LevelStreamingPlugin,
+            input::InputMapPlugin,
+            combat::CombatPlugin,
+            (
+                replay::ReplayPlugin,
+                rewind::RewindPlugin,
+                rollback::RollbackPlugin,
+            ),
             physics::PhysicsPlugin,
             physics::PhysicsDebugPlugin,
         ))
         .add_systems(Startup, systems::setup)
+        .add_systems(Update, systems::spawn_tile_collisions)
         .add_systems(
-            Update,
+            FixedUpdate,
             (
-                systems::spawn_tile_collisions,
-                systems::spawn_player,
+                (player::update_player_climbing, player::handle_input)
+                    .chain()
+                    .before(physics::Physics::Simulation)
+                    .run_if(levels::level_ready.and_then(not(rewind::rewinding))),
                 (player::update_player_grounded, player::handle_collision)
-                    .in_set(physics::Physics::Simulation),
-                player::handle_input,
-            )
-                .chain(),
+                    .chain()
+                    .in_set(physics::Physics::Simulation)
+                    .after(physics::simulate_actor_movement),
+            ),
         )
         .run();
 }
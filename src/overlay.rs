@@ -0,0 +1,66 @@
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContexts, EguiPlugin},
+    egui,
+};
+
+use crate::physics::PHYSICS_DIAGNOSTICS;
+
+/// On-screen frame rate and physics diagnostics, toggled with F3
+#[derive(Resource, Debug, Default)]
+pub struct DiagnosticsOverlay {
+    pub visible: bool,
+}
+
+pub fn toggle_overlay(keys: Res<ButtonInput<KeyCode>>, mut overlay: ResMut<DiagnosticsOverlay>) {
+    if keys.just_pressed(KeyCode::F3) {
+        overlay.visible = !overlay.visible;
+    }
+}
+
+pub fn draw_overlay(
+    mut contexts: EguiContexts,
+    overlay: Res<DiagnosticsOverlay>,
+    diagnostics: Res<DiagnosticsStore>,
+) {
+    if !overlay.visible {
+        return;
+    }
+
+    egui::Window::new("Diagnostics")
+        .anchor(egui::Align2::RIGHT_TOP, [-8., 8.])
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let paths = [FrameTimeDiagnosticsPlugin::FPS]
+                .into_iter()
+                .chain(PHYSICS_DIAGNOSTICS);
+
+            for diagnostic in paths.filter_map(|path| diagnostics.get(&path)) {
+                let Some(value) = diagnostic.smoothed() else {
+                    continue;
+                };
+
+                ui.monospace(format!(
+                    "{:<24} {value:>8.2}{}",
+                    diagnostic.path().as_str(),
+                    diagnostic.suffix
+                ));
+            }
+        });
+}
+
+pub struct DiagnosticsOverlayPlugin;
+
+impl Plugin for DiagnosticsOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+
+        app.init_resource::<DiagnosticsOverlay>()
+            .add_systems(Update, (toggle_overlay, draw_overlay).chain());
+    }
+}
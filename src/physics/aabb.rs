@@ -53,4 +53,10 @@ impl Shape for Aabb {
     fn as_typed_shape(&self) -> TypedShape {
         TypedShape::Aabb(*self)
     }
+
+    fn descriptor(&self) -> Option<ColliderDescriptor> {
+        Some(ColliderDescriptor::Aabb {
+            half_size: self.half_size,
+        })
+    }
 }
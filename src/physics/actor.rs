@@ -1,15 +1,65 @@
 use bevy::{math::vec2, prelude::*};
+use std::cell::Cell;
 
 use super::*;
 
 #[derive(Component, Default, Debug)]
 pub struct Actor;
 
+/// Actors with this component fall through `OneWay` solids
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component, Default)]
+pub struct DropThrough;
+
+/// The sides an actor was blocked on during its last move
+#[derive(Component, Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component, Default)]
+pub struct Contacts {
+    pub north: bool,
+    pub east: bool,
+    pub south: bool,
+    pub west: bool,
+}
+
+impl Contacts {
+    pub fn touching(&self, side: Cardinal) -> bool {
+        match side {
+            Cardinal::North => self.north,
+            Cardinal::East => self.east,
+            Cardinal::South => self.south,
+            Cardinal::West => self.west,
+        }
+    }
+
+    /// Every side that is touching, clockwise from north
+    pub fn sides(&self) -> Vec<Cardinal> {
+        [
+            Cardinal::North,
+            Cardinal::East,
+            Cardinal::South,
+            Cardinal::West,
+        ]
+        .into_iter()
+        .filter(|&side| self.touching(side))
+        .collect()
+    }
+
+    fn touch(&mut self, side: Cardinal) {
+        match side {
+            Cardinal::North => self.north = true,
+            Cardinal::East => self.east = true,
+            Cardinal::South => self.south = true,
+            Cardinal::West => self.west = true,
+        }
+    }
+}
+
 #[derive(Bundle, Default)]
 pub struct ActorBundle {
     pub actor: Actor,
     pub collider: Collider,
     pub velocity: Velocity,
+    pub contacts: Contacts,
     pub transform: Transform,
     pub global_transform: GlobalTransform,
     pub visibility: Visibility,
@@ -28,86 +78,310 @@ impl ActorBundle {
 }
 
 pub fn simulate_actor_movement(
-    time: Res<Time>,
+    time: Res<Time<Fixed>>,
     mut ev_collision: EventWriter<CollisionEvent>,
-    mut actor: Query<(Entity, &Collider, &mut Velocity, &mut Transform), With<Actor>>,
-    solids: Query<(&Collider, &Transform), (With<Solid>, Without<Actor>)>,
+    mut actor: Query<
+        (
+            Entity,
+            &Collider,
+            &mut Velocity,
+            &mut Transform,
+            &mut GlobalTransform,
+            Has<DropThrough>,
+            Option<&CornerCorrection>,
+            Option<&mut CoyoteTime>,
+            Option<&ColliderOffset>,
+            Option<&mut Contacts>,
+            Option<&StableId>,
+        ),
+        With<Actor>,
+    >,
+    solids: Query<
+        (
+            &Collider,
+            &GlobalTransform,
+            Option<&ColliderOffset>,
+            Has<OneWay>,
+        ),
+        (With<Solid>, Without<Actor>),
+    >,
+    mut trace: Option<ResMut<StepTrace>>,
+    mut counters: ResMut<PhysicsCounters>,
 ) {
-    for (entity, collider, mut velocity, mut transform) in &mut actor {
-        let delta = time.delta_seconds();
+    let delta = time.timestep().as_secs_f32();
+    let shape_tests = Cell::new(0);
+
+    if let Some(trace) = trace.as_mut() {
+        trace.begin_tick();
+    }
+
+    // `Query` order depends on how the world happens to store entities, sorting keeps ties
+    // between solids and the order actors move in the same on every peer of a rollback session
+    let mut solids = solids
+        .iter()
+        .filter_map(|(solid, solid_transform, solid_offset, one_way)| {
+            let TypedShape::Aabb(solid) = solid.as_typed_shape() else {
+                return None;
+            };
+
+            Some((
+                solid.aabb(collider_position(solid_transform, solid_offset)),
+                one_way,
+            ))
+        })
+        .collect::<Vec<_>>();
+    solids.sort_by(|(a, a_one_way), (b, b_one_way)| {
+        (a.min.y.total_cmp(&b.min.y))
+            .then(a.min.x.total_cmp(&b.min.x))
+            .then(a.max.y.total_cmp(&b.max.y))
+            .then(a.max.x.total_cmp(&b.max.x))
+            .then(a_one_way.cmp(b_one_way))
+    });
+
+    let mut actor = actor.iter_mut().collect::<Vec<_>>();
+    actor.sort_by_key(|(.., id)| id.copied());
+
+    for (
+        entity,
+        collider,
+        mut velocity,
+        mut transform,
+        mut global_transform,
+        drop_through,
+        correction,
+        coyote,
+        collider_offset,
+        mut contacts,
+        _,
+    ) in actor
+    {
+        if let Some(contacts) = contacts.as_mut() {
+            **contacts = Contacts::default();
+        }
+
         let dir = velocity.get_direction();
 
-        let amount_f = velocity.value * delta;
-        velocity.remainder += amount_f;
-        let mut amount_i = velocity.remainder.as_ivec2();
-        velocity.remainder -= amount_i.as_vec2();
+        let mut amount_i = velocity.step(delta);
+
+        let mut actor_trace = trace.is_some().then(|| ActorTrace {
+            path: vec![collider_position(&global_transform, collider_offset)],
+            ..default()
+        });
+
+        // the first solid that stops the actor from moving by `offset`
+        let blocking = |position: Vec2, offset: Vec2| {
+            solids
+                .iter()
+                .find(|(solid, one_way)| {
+                    shape_tests.set(shape_tests.get() + 1);
+
+                    // one way solids only block actors that start the step above them
+                    if *one_way
+                        && (drop_through || offset.y >= 0. || collider.collides(position, solid))
+                    {
+                        return false;
+                    }
+
+                    collider.collides(position + offset, solid)
+                })
+                .map(|(solid, _)| *solid)
+        };
+
+        let is_free = |position: Vec2| blocking(position, Vec2::ZERO).is_none();
 
         // move x
-        'move_x: loop {
+        loop {
             let dir_offset = vec2(dir.x, 0.);
 
-            if dir == Vec2::ZERO {
+            if dir_offset == Vec2::ZERO {
                 break;
             }
 
-            for (solid, solid_transform) in &solids {
-                let TypedShape::Aabb(solid) = solid.as_typed_shape() else {
-                    break;
-                };
+            let position = collider_position(&global_transform, collider_offset);
 
-                let solid = solid.aabb(solid_transform.translation.xy());
+            if let Some(solid) = blocking(position, dir_offset) {
+                // step up onto ledges that are only a few pixels higher
+                let nudge = correction
+                    .filter(|_| amount_i.x != 0)
+                    .and_then(|c| find_nudge(position, dir_offset, c.ledge, &[Vec2::Y], is_free));
 
-                if collider.collides(transform.translation.xy() + dir_offset, &solid) {
-                    ev_collision.send(CollisionEvent {
-                        entity,
-                        direction: Cardinal::from_vec2(dir_offset).unwrap(),
-                        solid,
-                    });
+                if let Some(nudge) = nudge {
+                    translate_collider(&mut transform, &mut global_transform, nudge);
+                    counters.pixel_steps += 1;
+                    if let Some(actor_trace) = &mut actor_trace {
+                        actor_trace
+                            .path
+                            .push(collider_position(&global_transform, collider_offset));
+                    }
+
+                    continue;
+                }
+
+                let direction = Cardinal::from_vec2(dir_offset).unwrap();
+
+                if let Some(actor_trace) = &mut actor_trace {
+                    actor_trace
+                        .contacts
+                        .push(Contact::new(position, direction, solid));
+                }
 
-                    break 'move_x;
+                if let Some(contacts) = contacts.as_mut() {
+                    contacts.touch(direction);
                 }
+
+                ev_collision.send(CollisionEvent {
+                    entity,
+                    direction,
+                    solid,
+                });
+
+                break;
             }
 
             if amount_i.x == 0 {
                 break;
             }
 
-            transform.translation.x += dir.x;
+            translate_collider(&mut transform, &mut global_transform, vec2(dir.x, 0.));
+            counters.pixel_steps += 1;
             amount_i.x -= dir.x as i32;
+            if let Some(actor_trace) = &mut actor_trace {
+                actor_trace
+                    .path
+                    .push(collider_position(&global_transform, collider_offset));
+            }
         }
 
+        let mut supported = false;
+
         // move y
-        'move_y: loop {
+        loop {
             let dir_offset = vec2(0., dir.y);
 
             if dir_offset == Vec2::ZERO {
                 break;
             }
 
-            for (solid, solid_transform) in &solids {
-                let TypedShape::Aabb(solid) = solid.as_typed_shape() else {
-                    break;
-                };
-
-                let solid = solid.aabb(solid_transform.translation.xy());
+            let position = collider_position(&global_transform, collider_offset);
 
-                if collider.collides(transform.translation.xy() + dir_offset, &solid) {
-                    ev_collision.send(CollisionEvent {
-                        entity,
-                        direction: Cardinal::from_vec2(dir_offset).unwrap(),
-                        solid,
+            if let Some(solid) = blocking(position, dir_offset) {
+                // slide around ceiling corners that are only clipped by a few pixels
+                let nudge = correction
+                    .filter(|_| dir.y > 0. && amount_i.y != 0)
+                    .and_then(|c| {
+                        find_nudge(
+                            position,
+                            dir_offset,
+                            c.ceiling,
+                            &[Vec2::X, Vec2::NEG_X],
+                            is_free,
+                        )
                     });
 
-                    break 'move_y;
+                if let Some(nudge) = nudge {
+                    translate_collider(&mut transform, &mut global_transform, nudge);
+                    counters.pixel_steps += 1;
+                    if let Some(actor_trace) = &mut actor_trace {
+                        actor_trace
+                            .path
+                            .push(collider_position(&global_transform, collider_offset));
+                    }
+
+                    continue;
+                }
+
+                supported = dir.y < 0.;
+
+                let direction = Cardinal::from_vec2(dir_offset).unwrap();
+
+                if let Some(actor_trace) = &mut actor_trace {
+                    actor_trace
+                        .contacts
+                        .push(Contact::new(position, direction, solid));
+                }
+
+                if let Some(contacts) = contacts.as_mut() {
+                    contacts.touch(direction);
                 }
+
+                ev_collision.send(CollisionEvent {
+                    entity,
+                    direction,
+                    solid,
+                });
+
+                break;
             }
 
             if amount_i.y == 0 {
                 break;
             }
 
-            transform.translation.y += dir.y;
+            translate_collider(&mut transform, &mut global_transform, vec2(0., dir.y));
+            counters.pixel_steps += 1;
             amount_i.y -= dir.y as i32;
+            if let Some(actor_trace) = &mut actor_trace {
+                actor_trace
+                    .path
+                    .push(collider_position(&global_transform, collider_offset));
+            }
+        }
+
+        if let Some(mut coyote) = coyote {
+            coyote.tick(supported, delta);
+        }
+
+        if let (Some(trace), Some(actor_trace)) = (trace.as_mut(), actor_trace) {
+            trace.record(entity, actor_trace);
+        }
+    }
+
+    counters.shape_tests += shape_tests.get();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::transform::systems::{propagate_transforms, sync_simple_transforms};
+
+    #[test]
+    fn collides_with_solids_at_their_world_position() {
+        let mut app = App::new();
+        app.insert_resource(Time::<Fixed>::from_hz(96.))
+            .init_resource::<PhysicsCounters>()
+            .add_event::<CollisionEvent>()
+            .add_systems(FixedFirst, (sync_simple_transforms, propagate_transforms))
+            .add_systems(FixedUpdate, simulate_actor_movement);
+
+        let world = app.world_mut();
+
+        // a level away from the origin, its floor sits at y = 500 in the world
+        world
+            .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+                1000., 500., 0.,
+            )))
+            .with_children(|level| {
+                level.spawn(SolidBundle::new(Vec2::new(0., -8.), Vec2::new(32., 8.)));
+            });
+
+        let actor = world
+            .spawn(ActorBundle::new(
+                Vec2::new(1000., 540.),
+                Collider::aabb(Vec2::new(4., 8.)),
+            ))
+            .id();
+
+        for _ in 0..60 {
+            world.get_mut::<Velocity>(actor).unwrap().value.y = -200.;
+            world.run_schedule(FixedFirst);
+            world.run_schedule(FixedUpdate);
         }
+
+        let transform = world.get::<Transform>(actor).unwrap();
+        assert_eq!(transform.translation.xy(), Vec2::new(1000., 508.));
+        assert_eq!(
+            world.get::<GlobalTransform>(actor).unwrap().translation(),
+            transform.translation
+        );
     }
 }
@@ -0,0 +1,54 @@
+use bevy::prelude::*;
+
+use super::*;
+
+/// A volume actors can move through freely, but that gameplay code can test overlaps against
+#[derive(Component, Default, Debug)]
+pub struct Area;
+
+#[derive(Bundle, Default)]
+pub struct AreaBundle {
+    pub area: Area,
+    pub collider: Collider,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub visibility: Visibility,
+    pub inherited_visibility: InheritedVisibility,
+    pub view_visibility: ViewVisibility,
+}
+
+impl AreaBundle {
+    pub fn new(pos: Vec2, half_size: Vec2) -> Self {
+        Self {
+            collider: Collider::aabb(half_size),
+            transform: Transform::from_xyz(pos.x, pos.y, 0.),
+            ..default()
+        }
+    }
+}
+
+/// Whether `collider` centered at `position` overlaps any of the aabb colliders in `others`
+pub fn overlaps_any<'a>(
+    collider: &Collider,
+    position: Vec2,
+    others: impl IntoIterator<
+        Item = (
+            &'a Collider,
+            &'a GlobalTransform,
+            Option<&'a ColliderOffset>,
+        ),
+    >,
+) -> bool {
+    others
+        .into_iter()
+        .any(|(other, other_transform, other_offset)| {
+            let TypedShape::Aabb(other) = other.as_typed_shape() else {
+                return false;
+            };
+
+            collider.collides(
+                position,
+                &other.aabb(collider_position(other_transform, other_offset)),
+            )
+        })
+}
@@ -0,0 +1,52 @@
+use bevy::{
+    math::bounding::{Aabb2d, BoundingCircle, BoundingVolume, IntersectsVolume},
+    prelude::*,
+};
+
+use super::*;
+
+/// A circle, named like this to not clash with Bevy's `Circle` primitive
+#[derive(Debug, Clone, Copy, Reflect)]
+pub struct Ball {
+    pub radius: f32,
+}
+
+impl Ball {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+
+    pub fn bounding_circle(&self, position: Vec2) -> BoundingCircle {
+        BoundingCircle::new(position, self.radius)
+    }
+}
+
+impl Shape for Ball {
+    fn collides(&self, position: Vec2, aabb: &Aabb2d) -> bool {
+        self.bounding_circle(position)
+            .intersects(&aabb.shrink(Vec2::splat(1.)))
+    }
+
+    fn get_collision_side(&self, position: Vec2, aabb: &Aabb2d) -> Option<Cardinal> {
+        if !self.bounding_circle(position).intersects(aabb) {
+            return None;
+        }
+
+        // the side only depends on where the closest point is, same as for the bounding box
+        Aabb::new(Vec2::splat(self.radius)).get_collision_side(position, aabb)
+    }
+
+    fn draw_gizmo(&self, gizmos: &mut Gizmos, position: Vec2, color: Color) {
+        gizmos.circle_2d(position, self.radius, color);
+    }
+
+    fn as_typed_shape(&self) -> TypedShape {
+        TypedShape::Ball(*self)
+    }
+
+    fn descriptor(&self) -> Option<ColliderDescriptor> {
+        Some(ColliderDescriptor::Circle {
+            radius: self.radius,
+        })
+    }
+}
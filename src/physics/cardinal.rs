@@ -1,6 +1,7 @@
 use bevy::{math::InvalidDirectionError, prelude::*};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, Reflect, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, Reflect, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Cardinal {
     West,
     North,
@@ -36,11 +37,7 @@ impl Cardinal {
         }
     }
 
-    pub fn isHorizontal(&self) -> bool {
-        *self == Self::West || *self == Self::East
-    }
-
-    pub fn isVertical(&self) -> bool {
+    pub fn is_vertical(&self) -> bool {
         *self == Self::North || *self == Self::South
     }
 
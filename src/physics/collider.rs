@@ -14,6 +14,9 @@ pub struct CollisionEvent {
 pub enum TypedShape {
     Aabb(Aabb),
     Ray(RayCast),
+    Ball(Ball),
+    Compound(Compound),
+    #[allow(dead_code, reason = "for game code matching on its own shapes")]
     Custom(CustomCollider),
     None,
 }
@@ -28,6 +31,11 @@ pub trait Shape: Send + Sync {
     fn as_typed_shape(&self) -> TypedShape {
         TypedShape::None
     }
+
+    /// How the shape is written down in scenes and asset files, if it can be
+    fn descriptor(&self) -> Option<ColliderDescriptor> {
+        None
+    }
 }
 
 #[derive(Clone, Deref)]
@@ -46,6 +54,14 @@ impl SharedShape {
         Self::new(RayCast::new(direction, length))
     }
 
+    pub fn ball(radius: f32) -> Self {
+        Self::new(Ball::new(radius))
+    }
+
+    pub fn compound(shapes: impl IntoIterator<Item = (Vec2, SharedShape)>) -> Self {
+        Self::new(Compound::new(shapes))
+    }
+
     pub fn custom(shape: impl Shape + 'static) -> Self {
         Self::new(CustomCollider::new(shape))
     }
@@ -54,6 +70,36 @@ impl SharedShape {
 #[derive(Component, Clone, Deref)]
 pub struct Collider(pub SharedShape);
 
+/// Shifts a collider away from its entity's origin, e.g. for sprites pivoting at their feet
+#[derive(Component, Reflect, Debug, Clone, Copy, Default, PartialEq, Deref, DerefMut)]
+#[reflect(Component, Default)]
+pub struct ColliderOffset(pub Vec2);
+
+/// Where the shape of a collider is centered in the world, every position tested against other
+/// colliders should come from here
+///
+/// Colliders can be children of levels and layers, so this uses the `GlobalTransform`, which
+/// the physics keeps current during fixed ticks
+pub fn collider_position(transform: &GlobalTransform, offset: Option<&ColliderOffset>) -> Vec2 {
+    transform.translation().xy() + offset.map_or(Vec2::ZERO, |offset| offset.0)
+}
+
+/// Moves an entity by `delta` in world space, updating its `GlobalTransform` right away so the
+/// rest of the tick sees the new position
+///
+/// Whatever is above a collider in the hierarchy is assumed to only translate, which is all
+/// LDtk levels and layers do
+pub fn translate_collider(
+    transform: &mut Transform,
+    global_transform: &mut GlobalTransform,
+    delta: Vec2,
+) {
+    let delta = delta.extend(0.);
+
+    transform.translation += delta;
+    *global_transform = GlobalTransform::from_translation(delta) * *global_transform;
+}
+
 impl Default for Collider {
     fn default() -> Self {
         Self::aabb(Vec2::splat(1.))
@@ -69,28 +115,39 @@ impl Collider {
         Self::new(SharedShape::aabb(half_size))
     }
 
-    pub fn ray_cast(half_size: Vec2) -> Self {
-        Self::new(SharedShape::aabb(half_size))
+    pub fn compound(shapes: impl IntoIterator<Item = (Vec2, SharedShape)>) -> Self {
+        Self::new(SharedShape::compound(shapes))
     }
 
+    #[allow(
+        dead_code,
+        reason = "for game specific shapes, the demo only uses built in ones"
+    )]
     pub fn custom(shape: impl Shape + 'static) -> Self {
         Self::new(SharedShape::custom(shape))
     }
-}
 
-pub fn draw_collider_gizmos(
-    mut gizmos: Gizmos,
-    query: Query<(&Collider, Option<&Actor>, &Transform)>,
-) {
-    for (collider, actor, transform) in &query {
-        collider.draw_gizmo(
-            &mut gizmos,
-            transform.translation.xy(),
-            if actor.is_some() {
-                Color::srgb(0., 0., 1.)
-            } else {
-                Color::srgb(0., 1., 0.)
-            },
-        );
+    /// How far the collider reaches from its position towards `direction`, `None` for custom
+    /// shapes
+    pub fn reach(&self, direction: Cardinal) -> Option<f32> {
+        shape_reach(&self.0, Vec2::ZERO, direction.as_vec2())
     }
 }
+
+fn shape_reach(shape: &SharedShape, offset: Vec2, direction: Vec2) -> Option<f32> {
+    Some(match shape.as_typed_shape() {
+        TypedShape::Aabb(aabb) => offset.dot(direction) + aabb.half_size.dot(direction.abs()),
+        TypedShape::Ray(ray) => {
+            let end = offset + ray.direction.as_vec2() * ray.length;
+
+            offset.dot(direction).max(end.dot(direction))
+        }
+        TypedShape::Ball(ball) => offset.dot(direction) + ball.radius,
+        TypedShape::Compound(compound) => compound
+            .shapes
+            .iter()
+            .map(|(shape_offset, shape)| shape_reach(shape, offset + *shape_offset, direction))
+            .reduce(|a, b| Some(a?.max(b?)))??,
+        TypedShape::Custom(_) | TypedShape::None => return None,
+    })
+}
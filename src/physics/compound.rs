@@ -0,0 +1,50 @@
+use bevy::{math::bounding::Aabb2d, prelude::*};
+
+use super::*;
+
+/// Several shapes moving together, each offset from the entity's position
+#[derive(Clone, Default)]
+pub struct Compound {
+    pub shapes: Vec<(Vec2, SharedShape)>,
+}
+
+impl Compound {
+    pub fn new(shapes: impl IntoIterator<Item = (Vec2, SharedShape)>) -> Self {
+        Self {
+            shapes: shapes.into_iter().collect(),
+        }
+    }
+}
+
+impl Shape for Compound {
+    fn collides(&self, position: Vec2, aabb: &Aabb2d) -> bool {
+        self.shapes
+            .iter()
+            .any(|(offset, shape)| shape.collides(position + *offset, aabb))
+    }
+
+    /// The side reported by the first shape touching `aabb`
+    fn get_collision_side(&self, position: Vec2, aabb: &Aabb2d) -> Option<Cardinal> {
+        self.shapes
+            .iter()
+            .find_map(|(offset, shape)| shape.get_collision_side(position + *offset, aabb))
+    }
+
+    fn draw_gizmo(&self, gizmos: &mut Gizmos, position: Vec2, color: Color) {
+        for (offset, shape) in &self.shapes {
+            shape.draw_gizmo(gizmos, position + *offset, color);
+        }
+    }
+
+    fn as_typed_shape(&self) -> TypedShape {
+        TypedShape::Compound(self.clone())
+    }
+
+    fn descriptor(&self) -> Option<ColliderDescriptor> {
+        self.shapes
+            .iter()
+            .map(|(offset, shape)| Some((*offset, shape.descriptor()?)))
+            .collect::<Option<_>>()
+            .map(ColliderDescriptor::Compound)
+    }
+}
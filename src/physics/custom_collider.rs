@@ -4,6 +4,9 @@ use bevy::{math::bounding::Aabb2d, prelude::*};
 
 use super::*;
 
+/// A game specific shape
+///
+/// Never described, a `ColliderDescriptor` can only rebuild it as one of the built in shapes
 #[derive(Clone, Deref)]
 pub struct CustomCollider(pub Arc<dyn Shape>);
 
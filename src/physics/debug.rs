@@ -0,0 +1,279 @@
+use bevy::{math::bounding::Aabb2d, prelude::*, utils::HashMap};
+
+use super::*;
+
+const ACTOR_COLOR: Color = Color::srgb(0., 0., 1.);
+const SOLID_COLOR: Color = Color::srgb(0., 1., 0.);
+const SENSOR_COLOR: Color = Color::srgb(1., 0.8, 0.);
+const COLLIDING_COLOR: Color = Color::srgb(1., 0., 0.);
+const CONTACT_COLOR: Color = Color::srgb(1., 0., 1.);
+const VELOCITY_COLOR: Color = Color::srgb(0., 1., 1.);
+const PATH_COLOR: Color = Color::srgb(1., 1., 1.);
+const GRID_COLOR: Color = Color::srgba(1., 1., 1., 0.15);
+
+/// Which debug layers `PhysicsDebugPlugin` draws
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct PhysicsDebugConfig {
+    pub colliders: bool,
+    /// Draw `Area`s in their own color
+    pub sensors: bool,
+    /// Draw colliders that touched something during the last frame in red
+    pub highlight_colliding: bool,
+    /// Contact points and normals from the last frame
+    pub contacts: bool,
+    pub velocities: bool,
+    /// Seconds of movement the velocity arrows are long
+    pub velocity_scale: f32,
+    /// Every pixel an actor moved through during the last frame
+    pub step_paths: bool,
+    /// A plain grid over the cells colliders touch, brighter where more of them share a cell
+    ///
+    /// There is no broadphase, actors still test every solid. This only shows how densely
+    /// colliders are packed
+    pub collider_grid: bool,
+    pub collider_grid_cell_size: f32,
+}
+
+impl Default for PhysicsDebugConfig {
+    fn default() -> Self {
+        Self {
+            colliders: true,
+            sensors: true,
+            highlight_colliding: true,
+            contacts: false,
+            velocities: false,
+            velocity_scale: 0.1,
+            step_paths: false,
+            collider_grid: false,
+            collider_grid_cell_size: 32.,
+        }
+    }
+}
+
+/// A point where an actor was stopped by a solid
+#[derive(Debug, Clone, Copy)]
+pub struct Contact {
+    pub point: Vec2,
+    /// Points away from the solid, towards the actor
+    pub normal: Vec2,
+    pub solid: Aabb2d,
+}
+
+impl Contact {
+    pub fn new(position: Vec2, direction: Cardinal, solid: Aabb2d) -> Self {
+        Self {
+            point: solid.closest_point(position),
+            normal: -direction.as_vec2(),
+            solid,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ActorTrace {
+    /// Positions after every pixel step, starting where the actor was before the tick
+    pub path: Vec<Vec2>,
+    pub contacts: Vec<Contact>,
+}
+
+/// What `simulate_actor_movement` did during every fixed tick of the last frame that ran one
+///
+/// Only recorded while the resource exists, which `PhysicsDebugPlugin` takes care of
+#[derive(Resource, Debug, Default)]
+pub struct StepTrace {
+    pub actors: HashMap<Entity, ActorTrace>,
+    /// Whether the next tick starts a new trace, the old one stays around to be drawn until then
+    expired: bool,
+}
+
+impl StepTrace {
+    /// Called before every tick, clears what is left over from an earlier frame
+    pub fn begin_tick(&mut self) {
+        if self.expired {
+            self.actors.clear();
+            self.expired = false;
+        }
+    }
+
+    /// Adds what an actor did during one tick to what it did earlier in the frame
+    pub fn record(&mut self, entity: Entity, actor_trace: ActorTrace) {
+        let trace = self.actors.entry(entity).or_default();
+
+        // every tick starts where the previous one ended
+        let skip = match (trace.path.last(), actor_trace.path.first()) {
+            (Some(end), Some(start)) => (end == start) as usize,
+            _ => 0,
+        };
+
+        trace.path.extend(actor_trace.path.into_iter().skip(skip));
+        trace.contacts.extend(actor_trace.contacts);
+    }
+
+    fn touched(&self, aabb: &Aabb2d) -> bool {
+        self.actors
+            .values()
+            .flat_map(|trace| &trace.contacts)
+            .any(|contact| contact.solid.min == aabb.min && contact.solid.max == aabb.max)
+    }
+}
+
+/// Lets the fixed ticks of the new frame start a new trace
+pub fn expire_step_trace(mut trace: ResMut<StepTrace>) {
+    trace.expired = true;
+}
+
+pub fn draw_collider_gizmos(
+    mut gizmos: Gizmos,
+    config: Res<PhysicsDebugConfig>,
+    trace: Res<StepTrace>,
+    query: Query<(
+        Entity,
+        &Collider,
+        Has<Actor>,
+        Has<Area>,
+        &GlobalTransform,
+        Option<&ColliderOffset>,
+    )>,
+) {
+    if !config.colliders {
+        return;
+    }
+
+    for (entity, collider, actor, area, transform, offset) in &query {
+        let position = collider_position(transform, offset);
+
+        let colliding = config.highlight_colliding
+            && if actor {
+                trace
+                    .actors
+                    .get(&entity)
+                    .is_some_and(|trace| !trace.contacts.is_empty())
+            } else {
+                match collider.as_typed_shape() {
+                    TypedShape::Aabb(aabb) => trace.touched(&aabb.aabb(position)),
+                    _ => false,
+                }
+            };
+
+        let color = if colliding {
+            COLLIDING_COLOR
+        } else if actor {
+            ACTOR_COLOR
+        } else if area && config.sensors {
+            SENSOR_COLOR
+        } else {
+            SOLID_COLOR
+        };
+
+        collider.draw_gizmo(&mut gizmos, position, color);
+    }
+}
+
+pub fn draw_contact_gizmos(
+    mut gizmos: Gizmos,
+    config: Res<PhysicsDebugConfig>,
+    trace: Res<StepTrace>,
+) {
+    if !config.contacts {
+        return;
+    }
+
+    for contact in trace.actors.values().flat_map(|trace| &trace.contacts) {
+        gizmos.circle_2d(contact.point, 1., CONTACT_COLOR);
+        gizmos.arrow_2d(
+            contact.point,
+            contact.point + contact.normal * 6.,
+            CONTACT_COLOR,
+        );
+    }
+}
+
+pub fn draw_velocity_gizmos(
+    mut gizmos: Gizmos,
+    config: Res<PhysicsDebugConfig>,
+    query: Query<(&Velocity, &GlobalTransform)>,
+) {
+    if !config.velocities {
+        return;
+    }
+
+    for (velocity, transform) in &query {
+        if velocity.value == Vec2::ZERO {
+            continue;
+        }
+
+        let position = transform.translation().xy();
+
+        gizmos.arrow_2d(
+            position,
+            position + velocity.value * config.velocity_scale,
+            VELOCITY_COLOR,
+        );
+    }
+}
+
+pub fn draw_step_path_gizmos(
+    mut gizmos: Gizmos,
+    config: Res<PhysicsDebugConfig>,
+    trace: Res<StepTrace>,
+) {
+    if !config.step_paths {
+        return;
+    }
+
+    for trace in trace.actors.values() {
+        if trace.path.len() > 1 {
+            gizmos.linestrip_2d(trace.path.iter().copied(), PATH_COLOR);
+        }
+
+        for &step in &trace.path {
+            gizmos.rect_2d(step, 0., Vec2::ONE, PATH_COLOR);
+        }
+    }
+}
+
+pub fn draw_collider_grid_gizmos(
+    mut gizmos: Gizmos,
+    config: Res<PhysicsDebugConfig>,
+    query: Query<(&Collider, &GlobalTransform, Option<&ColliderOffset>)>,
+) {
+    if !config.collider_grid || config.collider_grid_cell_size <= 0. {
+        return;
+    }
+
+    let cell_size = config.collider_grid_cell_size;
+    let mut cells: HashMap<IVec2, usize> = HashMap::new();
+
+    for (collider, transform, offset) in &query {
+        let position = collider_position(transform, offset);
+
+        // anything that isn't an aabb is bucketed by its center
+        let aabb = match collider.as_typed_shape() {
+            TypedShape::Aabb(aabb) => aabb.aabb(position),
+            _ => Aabb2d::new(position, Vec2::ZERO),
+        };
+
+        let min = (aabb.min / cell_size).floor().as_ivec2();
+        let max = (aabb.max / cell_size).floor().as_ivec2();
+
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                *cells.entry(IVec2::new(x, y)).or_default() += 1;
+            }
+        }
+    }
+
+    for (cell, count) in cells {
+        let center = (cell.as_vec2() + 0.5) * cell_size;
+        // busier cells are drawn brighter
+        let alpha = (count as f32 * GRID_COLOR.alpha()).min(1.);
+
+        gizmos.rect_2d(
+            center,
+            0.,
+            Vec2::splat(cell_size),
+            GRID_COLOR.with_alpha(alpha),
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+use super::*;
+
+/// Plain data version of a `Collider`, for reflection, scenes and asset files
+///
+/// New colliders get one describing them. When it is edited, from the inspector or by a
+/// `ColliderAsset`, the collider is rebuilt from it. Custom colliders are neither described
+/// nor rebuilt
+#[derive(Component, Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Component, no_field_bounds)]
+pub enum ColliderDescriptor {
+    Aabb {
+        half_size: Vec2,
+    },
+    Ray {
+        direction: Cardinal,
+        length: f32,
+    },
+    Circle {
+        radius: f32,
+    },
+    /// Shapes with their offset from the entity's position
+    Compound(Vec<(Vec2, ColliderDescriptor)>),
+}
+
+impl Default for ColliderDescriptor {
+    fn default() -> Self {
+        Self::Aabb {
+            half_size: Vec2::splat(1.),
+        }
+    }
+}
+
+impl ColliderDescriptor {
+    pub fn shape(&self) -> SharedShape {
+        match self {
+            Self::Aabb { half_size } => SharedShape::aabb(*half_size),
+            Self::Ray { direction, length } => SharedShape::ray_cast(*direction, *length),
+            Self::Circle { radius } => SharedShape::ball(*radius),
+            Self::Compound(shapes) => SharedShape::compound(
+                shapes
+                    .iter()
+                    .map(|(offset, descriptor)| (*offset, descriptor.shape())),
+            ),
+        }
+    }
+}
+
+impl From<&ColliderDescriptor> for Collider {
+    fn from(descriptor: &ColliderDescriptor) -> Self {
+        Collider::new(descriptor.shape())
+    }
+}
+
+/// A `ColliderDescriptor` loaded from a `.collider.ron` file
+///
+/// Entities with a `Handle<ColliderAsset>` get its descriptor, and with that its collider,
+/// whenever the file is loaded or changes
+#[derive(Asset, TypePath, Debug, Clone, Deref, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ColliderAsset(pub ColliderDescriptor);
+
+#[derive(Default)]
+pub struct ColliderAssetLoader;
+
+impl AssetLoader for ColliderAssetLoader {
+    type Asset = ColliderAsset;
+    type Settings = ();
+    type Error = io::Error;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _: &'a (),
+        _: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        ron::de::from_bytes(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["collider.ron"]
+    }
+}
+
+pub fn apply_collider_assets(
+    mut commands: Commands,
+    mut ev_asset: EventReader<AssetEvent<ColliderAsset>>,
+    assets: Res<Assets<ColliderAsset>>,
+    query: Query<(Entity, &Handle<ColliderAsset>)>,
+    added: Query<(Entity, &Handle<ColliderAsset>), Added<Handle<ColliderAsset>>>,
+) {
+    let changed = ev_asset
+        .read()
+        .filter_map(|ev| match ev {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let updated = query
+        .iter()
+        .filter(|(_, handle)| changed.contains(&handle.id()))
+        .chain(&added);
+
+    for (entity, handle) in updated {
+        if let Some(asset) = assets.get(handle) {
+            commands.entity(entity).insert(asset.0.clone());
+        }
+    }
+}
+
+/// Rebuilds the `Collider` of entities whose `ColliderDescriptor` was edited
+pub fn sync_colliders_from_descriptors(
+    mut commands: Commands,
+    mut query: Query<
+        (Entity, &ColliderDescriptor, Option<&mut Collider>),
+        Changed<ColliderDescriptor>,
+    >,
+) {
+    for (entity, descriptor, collider) in &mut query {
+        match collider {
+            // custom shapes would be replaced by a built in one
+            Some(collider) if matches!(collider.as_typed_shape(), TypedShape::Custom(_)) => (),
+            // just described by `describe_new_colliders`, rebuilding it would change nothing
+            Some(collider) if collider.descriptor().as_ref() == Some(descriptor) => (),
+            Some(mut collider) => *collider = descriptor.into(),
+            None => {
+                commands.entity(entity).insert(Collider::from(descriptor));
+            }
+        }
+    }
+}
+
+/// Gives new colliders a `ColliderDescriptor`, if their shape can be described
+pub fn describe_new_colliders(
+    mut commands: Commands,
+    query: Query<(Entity, &Collider), (Added<Collider>, Without<ColliderDescriptor>)>,
+) {
+    for (entity, collider) in &query {
+        if let Some(descriptor) = collider.descriptor() {
+            commands.entity(entity).insert(descriptor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_systems(
+            PreUpdate,
+            (sync_colliders_from_descriptors, describe_new_colliders).chain(),
+        );
+        app
+    }
+
+    #[test]
+    fn rebuilds_colliders_from_edits() {
+        let mut app = app();
+        let entity = app.world_mut().spawn(Collider::aabb(Vec2::splat(4.))).id();
+
+        app.update();
+        app.update();
+
+        let edited = ColliderDescriptor::Circle { radius: 3. };
+        *app.world_mut()
+            .get_mut::<ColliderDescriptor>(entity)
+            .unwrap() = edited.clone();
+
+        app.update();
+
+        let collider = app.world().get::<Collider>(entity).unwrap();
+        assert_eq!(collider.descriptor(), Some(edited));
+    }
+
+    #[test]
+    fn keeps_custom_colliders() {
+        let mut app = app();
+        let entity = app
+            .world_mut()
+            .spawn(Collider::custom(Aabb::new(Vec2::splat(4.))))
+            .id();
+
+        app.update();
+        app.update();
+
+        let world = app.world();
+        assert!(world.get::<ColliderDescriptor>(entity).is_none());
+        assert!(matches!(
+            world.get::<Collider>(entity).unwrap().as_typed_shape(),
+            TypedShape::Custom(_)
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+use bevy::{
+    diagnostic::{DiagnosticPath, Diagnostics},
+    prelude::*,
+    utils::{Duration, Instant},
+};
+
+use super::*;
+
+pub const SIMULATION_TIME: DiagnosticPath = DiagnosticPath::const_new("physics/simulation_time");
+pub const ACTORS: DiagnosticPath = DiagnosticPath::const_new("physics/actors");
+pub const SOLIDS: DiagnosticPath = DiagnosticPath::const_new("physics/solids");
+pub const PIXEL_STEPS: DiagnosticPath = DiagnosticPath::const_new("physics/pixel_steps");
+pub const SHAPE_TESTS: DiagnosticPath = DiagnosticPath::const_new("physics/shape_tests");
+
+/// Every diagnostic `PhysicsPlugin` records, once per frame
+pub const PHYSICS_DIAGNOSTICS: [DiagnosticPath; 5] =
+    [SIMULATION_TIME, ACTORS, SOLIDS, PIXEL_STEPS, SHAPE_TESTS];
+
+/// Work done by the simulation since the diagnostics were last recorded,
+/// summed over however many fixed ticks ran this frame
+#[derive(Resource, Debug, Default)]
+pub struct PhysicsCounters {
+    pub simulation_time: Duration,
+    pub pixel_steps: u32,
+    pub shape_tests: u32,
+    started: Option<Instant>,
+}
+
+pub fn start_simulation_timer(mut counters: ResMut<PhysicsCounters>) {
+    counters.started = Some(Instant::now());
+}
+
+pub fn stop_simulation_timer(mut counters: ResMut<PhysicsCounters>) {
+    if let Some(started) = counters.started.take() {
+        counters.simulation_time += started.elapsed();
+    }
+}
+
+pub fn record_physics_diagnostics(
+    mut diagnostics: Diagnostics,
+    mut counters: ResMut<PhysicsCounters>,
+    actors: Query<(), With<Actor>>,
+    solids: Query<(), With<Solid>>,
+) {
+    let counters = std::mem::take(counters.as_mut());
+
+    diagnostics.add_measurement(&SIMULATION_TIME, || {
+        counters.simulation_time.as_secs_f64() * 1000.
+    });
+    diagnostics.add_measurement(&ACTORS, || actors.iter().len() as f64);
+    diagnostics.add_measurement(&SOLIDS, || solids.iter().len() as f64);
+    diagnostics.add_measurement(&PIXEL_STEPS, || counters.pixel_steps as f64);
+    diagnostics.add_measurement(&SHAPE_TESTS, || counters.shape_tests as f64);
+}
@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+
+/// Opt-in nudging of actors that clip the corner of a solid by a few pixels
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct CornerCorrection {
+    /// How far an actor moving up is pushed sideways around the corner of a ceiling
+    pub ceiling: i32,
+    /// How far an actor moving sideways is pushed up onto the edge of a ledge
+    pub ledge: i32,
+}
+
+impl Default for CornerCorrection {
+    fn default() -> Self {
+        Self {
+            ceiling: 4,
+            ledge: 2,
+        }
+    }
+}
+
+/// Keeps an actor counting as supported for a short while after walking off an edge
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+#[reflect(Component)]
+pub struct CoyoteTime {
+    pub duration: f32,
+    pub remaining: f32,
+}
+
+impl CoyoteTime {
+    pub fn new(duration: f32) -> Self {
+        Self {
+            duration,
+            remaining: 0.,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0.
+    }
+
+    /// Spends the remaining time, e.g. when jumping, so it can't be used twice
+    pub fn consume(&mut self) {
+        self.remaining = 0.;
+    }
+
+    pub fn tick(&mut self, supported: bool, delta: f32) {
+        self.remaining = if supported {
+            self.duration
+        } else {
+            (self.remaining - delta).max(0.)
+        };
+    }
+}
+
+/// Finds the smallest shift along one of `axes`, up to `max` pixels, after which `offset` is free
+///
+/// Every pixel on the way has to be free too, so actors are never nudged through thin walls
+pub fn find_nudge(
+    position: Vec2,
+    offset: Vec2,
+    max: i32,
+    axes: &[Vec2],
+    is_free: impl Fn(Vec2) -> bool,
+) -> Option<Vec2> {
+    for n in 1..=max {
+        for &axis in axes {
+            let shift = axis * n as f32;
+
+            if (1..=n).all(|k| is_free(position + axis * k as f32))
+                && is_free(position + shift + offset)
+            {
+                return Some(shift);
+            }
+        }
+    }
+
+    None
+}
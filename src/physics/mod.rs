@@ -1,14 +1,25 @@
 mod aabb;
 mod actor;
+mod area;
+mod ball;
 mod cardinal;
 mod collider;
+mod compound;
 mod custom_collider;
+mod debug;
+mod descriptor;
+mod diagnostics;
+mod fnv;
+mod forgiveness;
 mod plugin;
 mod ray_cast;
+mod snapshot;
 mod solid;
+mod stable_id;
 mod velocity;
 
 pub use crate::physics::{
-    aabb::*, actor::*, cardinal::*, collider::*, custom_collider::*, plugin::*, ray_cast::*,
-    solid::*, velocity::*,
+    aabb::*, actor::*, area::*, ball::*, cardinal::*, collider::*, compound::*, custom_collider::*,
+    debug::*, descriptor::*, diagnostics::*, fnv::*, forgiveness::*, plugin::*, ray_cast::*,
+    snapshot::*, solid::*, stable_id::*, velocity::*,
 };
@@ -1,4 +1,11 @@
-use bevy::{prelude::*, transform::TransformSystem};
+use bevy::{
+    diagnostic::{Diagnostic, RegisterDiagnostic},
+    prelude::*,
+    transform::{
+        systems::{propagate_transforms, sync_simple_transforms},
+        TransformSystem,
+    },
+};
 
 use super::*;
 
@@ -13,15 +20,57 @@ pub struct PhysicsPlugin;
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Time::<Fixed>::from_hz(96.0))
-            .register_type::<Velocity>()
+            .register_type::<CornerCorrection>()
+            .register_type::<ColliderOffset>()
+            .register_type::<StableId>()
+            .snapshot_component::<Transform>()
+            .snapshot_component::<Velocity>()
+            .snapshot_component::<CoyoteTime>()
+            .snapshot_component::<DropThrough>()
+            .snapshot_component::<Contacts>()
             .add_event::<CollisionEvent>()
+            .register_type::<ColliderDescriptor>()
+            .init_asset::<ColliderAsset>()
+            .init_asset_loader::<ColliderAssetLoader>()
+            // before the fixed ticks of the frame, so they already use the new shapes
+            .add_systems(
+                PreUpdate,
+                (
+                    apply_collider_assets,
+                    sync_colliders_from_descriptors,
+                    describe_new_colliders,
+                )
+                    .chain(),
+            )
+            // the simulation runs on fixed ticks so that the same inputs always produce
+            // the same trajectory, regardless of frame rate
+            .configure_sets(FixedUpdate, Physics::Simulation)
             .configure_sets(
                 Update,
-                (Physics::Simulation, Physics::Debug)
-                    .chain()
-                    .before(TransformSystem::TransformPropagate),
+                Physics::Debug.before(TransformSystem::TransformPropagate),
+            )
+            .add_systems(
+                FixedUpdate,
+                simulate_actor_movement.in_set(Physics::Simulation),
             )
-            .add_systems(Update, simulate_actor_movement.in_set(Physics::Simulation));
+            // colliders are tested at their world position, which has to account for anything
+            // moved or reparented since the last frame's propagation
+            .add_systems(FixedFirst, (sync_simple_transforms, propagate_transforms))
+            .init_resource::<PhysicsCounters>()
+            .register_diagnostic(Diagnostic::new(SIMULATION_TIME).with_suffix("ms"))
+            .register_diagnostic(Diagnostic::new(ACTORS))
+            .register_diagnostic(Diagnostic::new(SOLIDS))
+            .register_diagnostic(Diagnostic::new(PIXEL_STEPS))
+            .register_diagnostic(Diagnostic::new(SHAPE_TESTS))
+            .add_systems(
+                FixedUpdate,
+                (
+                    start_simulation_timer.before(Physics::Simulation),
+                    stop_simulation_timer.after(Physics::Simulation),
+                ),
+            )
+            // the fixed ticks of a frame all run before `Update`
+            .add_systems(Update, record_physics_diagnostics);
     }
 }
 
@@ -29,6 +78,21 @@ pub struct PhysicsDebugPlugin;
 
 impl Plugin for PhysicsDebugPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, draw_collider_gizmos.in_set(Physics::Debug));
+        app.init_resource::<PhysicsDebugConfig>()
+            .init_resource::<StepTrace>()
+            .register_type::<PhysicsDebugConfig>()
+            .add_systems(First, expire_step_trace)
+            .add_systems(
+                Update,
+                (
+                    draw_collider_grid_gizmos,
+                    draw_collider_gizmos,
+                    draw_step_path_gizmos,
+                    draw_velocity_gizmos,
+                    draw_contact_gizmos,
+                )
+                    .chain()
+                    .in_set(Physics::Debug),
+            );
     }
 }
@@ -45,4 +45,11 @@ impl Shape for RayCast {
     fn as_typed_shape(&self) -> TypedShape {
         TypedShape::Ray(*self)
     }
+
+    fn descriptor(&self) -> Option<ColliderDescriptor> {
+        Some(ColliderDescriptor::Ray {
+            direction: self.direction,
+            length: self.length,
+        })
+    }
 }
@@ -0,0 +1,504 @@
+use bevy::{
+    ecs::reflect::ReflectComponent,
+    prelude::*,
+    reflect::{GetTypeRegistration, ReflectMut, ReflectRef, TypeInfo, TypeRegistry},
+    utils::HashMap,
+};
+use std::{any::TypeId, fmt, hash::Hasher};
+
+use super::*;
+
+/// Component types captured by `PhysicsSnapshot`, in the order they are written
+///
+/// Every type has to be registered for reflection with `#[reflect(Component)]`
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SnapshotComponents(Vec<(TypeId, &'static str)>);
+
+impl SnapshotComponents {
+    pub fn add<T: Component>(&mut self) -> &mut Self {
+        let id = TypeId::of::<T>();
+
+        if self.0.iter().all(|(other, _)| *other != id) {
+            self.0.push((id, std::any::type_name::<T>()));
+        }
+
+        self
+    }
+}
+
+pub trait SnapshotAppExt {
+    /// Registers `T` for reflection and includes it in every `PhysicsSnapshot`
+    fn snapshot_component<T: Component + GetTypeRegistration>(&mut self) -> &mut Self;
+}
+
+impl SnapshotAppExt for App {
+    fn snapshot_component<T: Component + GetTypeRegistration>(&mut self) -> &mut Self {
+        self.register_type::<T>()
+            .init_resource::<SnapshotComponents>()
+            .world_mut()
+            .resource_mut::<SnapshotComponents>()
+            .add::<T>();
+
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// A snapshotted type isn't registered with `#[reflect(Component)]`
+    Unregistered(&'static str),
+    /// The type contains something other than plain numbers, structs, tuples and lists
+    Unsupported(String),
+    /// The component has to be inserted but the type doesn't reflect `Default`
+    NoDefault(String),
+    /// The snapshot was taken with a different set of `SnapshotComponents`
+    LayoutMismatch,
+    /// More than one entity has this `StableId`, so they can't be told apart on restore
+    DuplicateId(u64),
+    Truncated,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unregistered(name) => write!(f, "`{name}` is not registered as a component"),
+            Self::Unsupported(path) => write!(f, "can't snapshot values of type `{path}`"),
+            Self::NoDefault(path) => write!(f, "can't insert `{path}` without a default"),
+            Self::LayoutMismatch => write!(f, "snapshot was taken with different components"),
+            Self::DuplicateId(id) => write!(f, "more than one entity has the id `{id:#x}`"),
+            Self::Truncated => write!(f, "snapshot ended early"),
+        }
+    }
+}
+
+/// The state of every entity with a `StableId`, packed into bytes
+///
+/// Only the `SnapshotComponents` are written, field by field through reflection. Entities are
+/// matched up by their `StableId` on restore, so a snapshot applies to any world that spawned
+/// the same entities, whatever order they were spawned in
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PhysicsSnapshot {
+    bytes: Vec<u8>,
+}
+
+impl PhysicsSnapshot {
+    pub fn capture(world: &World) -> Result<Self, SnapshotError> {
+        let registry = world.resource::<AppTypeRegistry>().read();
+        let components = components(world, &registry)?;
+
+        let mut entities = world
+            .iter_entities()
+            .filter_map(|entity| Some((*entity.get::<StableId>()?, entity)))
+            .collect::<Vec<_>>();
+        // sorted so the same world always gives the same bytes
+        entities.sort_by_key(|(id, _)| *id);
+
+        if let Some(pair) = entities.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(SnapshotError::DuplicateId(pair[0].0 .0));
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend(layout(&components).to_le_bytes());
+        bytes.extend((entities.len() as u32).to_le_bytes());
+
+        for (id, entity) in entities {
+            bytes.extend(id.0.to_le_bytes());
+
+            for (reflect_component, _) in &components {
+                let Some(component) = reflect_component.reflect(entity) else {
+                    bytes.push(0);
+                    continue;
+                };
+
+                let mut data = Vec::new();
+                write_value(component, &mut data)?;
+
+                bytes.push(1);
+                bytes.extend((data.len() as u32).to_le_bytes());
+                bytes.extend(data);
+            }
+        }
+
+        Ok(Self { bytes })
+    }
+
+    /// Writes the snapshotted components back, inserting and removing them where needed
+    ///
+    /// Entities that no longer exist are skipped. An error can leave the world partially restored
+    pub fn restore(&self, world: &mut World) -> Result<(), SnapshotError> {
+        let registry = world.resource::<AppTypeRegistry>().clone();
+        let registry = registry.read();
+        let components = components(world, &registry)?;
+
+        let ids: HashMap<StableId, Entity> = world
+            .query::<(Entity, &StableId)>()
+            .iter(world)
+            .map(|(entity, id)| (*id, entity))
+            .collect();
+
+        let mut reader = Reader(&self.bytes);
+
+        if reader.u64()? != layout(&components) {
+            return Err(SnapshotError::LayoutMismatch);
+        }
+
+        for _ in 0..reader.u32()? {
+            let id = StableId(reader.u64()?);
+            let mut entity = ids
+                .get(&id)
+                .and_then(|&entity| world.get_entity_mut(entity));
+
+            for (reflect_component, path) in &components {
+                let data = match reader.u8()? {
+                    0 => None,
+                    _ => {
+                        let len = reader.u32()? as usize;
+                        Some(Reader(reader.take(len)?))
+                    }
+                };
+
+                let Some(entity) = entity.as_mut() else {
+                    continue;
+                };
+
+                let Some(mut data) = data else {
+                    reflect_component.remove(entity);
+                    continue;
+                };
+
+                if let Some(mut component) = reflect_component.reflect_mut(&mut *entity) {
+                    read_value(component.as_reflect_mut(), &registry, &mut data)?;
+                    continue;
+                }
+
+                let Some(mut component) = registry
+                    .get_with_type_path(path)
+                    .and_then(|registration| registration.data::<ReflectDefault>())
+                    .map(|default| default.default())
+                else {
+                    return Err(SnapshotError::NoDefault(path.to_string()));
+                };
+
+                read_value(component.as_mut(), &registry, &mut data)?;
+                reflect_component.insert(entity, component.as_ref(), &registry);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    /// Wraps bytes from `to_bytes`, they are only checked when restoring
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+}
+
+fn components<'a>(
+    world: &World,
+    registry: &'a TypeRegistry,
+) -> Result<Vec<(&'a ReflectComponent, &'a str)>, SnapshotError> {
+    let Some(components) = world.get_resource::<SnapshotComponents>() else {
+        return Ok(Vec::new());
+    };
+
+    components
+        .0
+        .iter()
+        .map(|&(id, name)| {
+            registry
+                .get(id)
+                .and_then(|registration| {
+                    let path = registration.type_info().type_path();
+                    registration.data::<ReflectComponent>().map(|c| (c, path))
+                })
+                .ok_or(SnapshotError::Unregistered(name))
+        })
+        .collect()
+}
+
+/// Hash of the snapshotted type paths, so snapshots aren't applied to a different layout
+fn layout(components: &[(&ReflectComponent, &str)]) -> u64 {
+    let mut hasher = Fnv1a::new();
+
+    for (_, path) in components {
+        hasher.write(path.as_bytes());
+        hasher.write_u8(0xff);
+    }
+
+    hasher.finish()
+}
+
+fn write_value(value: &dyn Reflect, bytes: &mut Vec<u8>) -> Result<(), SnapshotError> {
+    match value.reflect_ref() {
+        ReflectRef::Struct(value) => value
+            .iter_fields()
+            .try_for_each(|field| write_value(field, bytes)),
+        ReflectRef::TupleStruct(value) => value
+            .iter_fields()
+            .try_for_each(|field| write_value(field, bytes)),
+        ReflectRef::Tuple(value) => value
+            .iter_fields()
+            .try_for_each(|field| write_value(field, bytes)),
+        ReflectRef::Array(value) => value.iter().try_for_each(|item| write_value(item, bytes)),
+        ReflectRef::List(value) => {
+            bytes.extend((value.len() as u32).to_le_bytes());
+            value.iter().try_for_each(|item| write_value(item, bytes))
+        }
+        ReflectRef::Value(value) => {
+            let any = value.as_any();
+
+            if let Some(value) = any.downcast_ref::<f32>() {
+                bytes.extend(value.to_le_bytes());
+            } else if let Some(value) = any.downcast_ref::<f64>() {
+                bytes.extend(value.to_le_bytes());
+            } else if let Some(value) = any.downcast_ref::<i32>() {
+                bytes.extend(value.to_le_bytes());
+            } else if let Some(value) = any.downcast_ref::<u32>() {
+                bytes.extend(value.to_le_bytes());
+            } else if let Some(value) = any.downcast_ref::<u64>() {
+                bytes.extend(value.to_le_bytes());
+            } else if let Some(value) = any.downcast_ref::<usize>() {
+                bytes.extend((*value as u64).to_le_bytes());
+            } else if let Some(value) = any.downcast_ref::<bool>() {
+                bytes.push(*value as u8);
+            } else {
+                return Err(SnapshotError::Unsupported(value.reflect_type_path().into()));
+            }
+
+            Ok(())
+        }
+        ReflectRef::Map(_) | ReflectRef::Enum(_) => {
+            Err(SnapshotError::Unsupported(value.reflect_type_path().into()))
+        }
+    }
+}
+
+fn read_value(
+    value: &mut dyn Reflect,
+    registry: &TypeRegistry,
+    reader: &mut Reader,
+) -> Result<(), SnapshotError> {
+    let path = value.reflect_type_path().to_string();
+
+    match value.reflect_mut() {
+        ReflectMut::Struct(value) => (0..value.field_len())
+            .try_for_each(|i| read_value(value.field_at_mut(i).unwrap(), registry, reader)),
+        ReflectMut::TupleStruct(value) => (0..value.field_len())
+            .try_for_each(|i| read_value(value.field_mut(i).unwrap(), registry, reader)),
+        ReflectMut::Tuple(value) => (0..value.field_len())
+            .try_for_each(|i| read_value(value.field_mut(i).unwrap(), registry, reader)),
+        ReflectMut::Array(value) => (0..value.len())
+            .try_for_each(|i| read_value(value.get_mut(i).unwrap(), registry, reader)),
+        ReflectMut::List(value) => {
+            let len = reader.u32()? as usize;
+
+            while value.len() > len {
+                value.pop();
+            }
+
+            // new items start out as copies of the first, or the default of the item type when
+            // there is none, they are overwritten anyway
+            while value.len() < len {
+                let item = match value.get(0) {
+                    Some(first) => first.clone_value(),
+                    None => value
+                        .get_represented_type_info()
+                        .and_then(|info| match info {
+                            TypeInfo::List(info) => registry.get(info.item_type_id()),
+                            _ => None,
+                        })
+                        .and_then(|registration| registration.data::<ReflectDefault>())
+                        .map(|default| default.default())
+                        .ok_or_else(|| SnapshotError::NoDefault(path.clone()))?,
+                };
+
+                value.push(item);
+            }
+
+            (0..len).try_for_each(|i| read_value(value.get_mut(i).unwrap(), registry, reader))
+        }
+        ReflectMut::Value(value) => {
+            let any = value.as_any_mut();
+
+            if let Some(value) = any.downcast_mut::<f32>() {
+                *value = f32::from_bits(reader.u32()?);
+            } else if let Some(value) = any.downcast_mut::<f64>() {
+                *value = f64::from_bits(reader.u64()?);
+            } else if let Some(value) = any.downcast_mut::<i32>() {
+                *value = reader.u32()? as i32;
+            } else if let Some(value) = any.downcast_mut::<u32>() {
+                *value = reader.u32()?;
+            } else if let Some(value) = any.downcast_mut::<u64>() {
+                *value = reader.u64()?;
+            } else if let Some(value) = any.downcast_mut::<usize>() {
+                *value = reader.u64()? as usize;
+            } else if let Some(value) = any.downcast_mut::<bool>() {
+                *value = reader.u8()? != 0;
+            } else {
+                return Err(SnapshotError::Unsupported(path));
+            }
+
+            Ok(())
+        }
+        ReflectMut::Map(_) | ReflectMut::Enum(_) => Err(SnapshotError::Unsupported(path)),
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.0.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.snapshot_component::<Transform>()
+            .snapshot_component::<Velocity>()
+            .snapshot_component::<Contacts>();
+        app
+    }
+
+    fn velocity(value: Vec2, remainder: Vec2) -> Velocity {
+        Velocity { value, remainder }
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let mut app = app();
+        let world = app.world_mut();
+
+        let falling = world
+            .spawn((
+                StableId(1),
+                Transform::from_xyz(12., -3.5, 0.),
+                velocity(Vec2::new(0., -200.), Vec2::new(0.25, -0.5)),
+            ))
+            .id();
+        let grounded = world
+            .spawn((
+                StableId(2),
+                Transform::from_xyz(40., 8., 0.),
+                velocity(Vec2::new(30., 0.), Vec2::ZERO),
+                Contacts {
+                    south: true,
+                    ..default()
+                },
+            ))
+            .id();
+
+        let snapshot = PhysicsSnapshot::capture(world).unwrap();
+        let bytes = snapshot.to_bytes();
+
+        // everything captured changes, including which components exist
+        *world.get_mut::<Transform>(falling).unwrap() = Transform::from_xyz(0., 0., 0.);
+        *world.get_mut::<Velocity>(falling).unwrap() = velocity(Vec2::ONE, Vec2::ONE);
+        world.entity_mut(falling).insert(Contacts {
+            north: true,
+            ..default()
+        });
+        world.entity_mut(grounded).remove::<Contacts>();
+        world.get_mut::<Velocity>(grounded).unwrap().value.x = -30.;
+
+        PhysicsSnapshot::from_bytes(bytes).restore(world).unwrap();
+
+        assert_eq!(PhysicsSnapshot::capture(world).unwrap(), snapshot);
+        assert_eq!(
+            world.get::<Transform>(falling).unwrap().translation,
+            Vec3::new(12., -3.5, 0.)
+        );
+        assert_eq!(
+            world.get::<Velocity>(falling).unwrap().remainder,
+            Vec2::new(0.25, -0.5)
+        );
+        assert!(world.get::<Contacts>(falling).is_none());
+        assert_eq!(
+            world.get::<Contacts>(grounded).unwrap().sides(),
+            vec![Cardinal::South]
+        );
+        assert_eq!(world.get::<Velocity>(grounded).unwrap().value.x, 30.);
+    }
+
+    #[test]
+    fn matches_entities_by_stable_id() {
+        let mut first = app();
+        first
+            .world_mut()
+            .spawn((StableId(1), Transform::from_xyz(1., 0., 0.)));
+        first
+            .world_mut()
+            .spawn((StableId(2), Transform::from_xyz(2., 0., 0.)));
+
+        let snapshot = PhysicsSnapshot::capture(first.world()).unwrap();
+
+        // spawned the other way around, with something in between shifting the entity ids
+        let mut second = app();
+        let world = second.world_mut();
+        let two = world.spawn((StableId(2), Transform::default())).id();
+        world.spawn(Transform::default());
+        let one = world.spawn((StableId(1), Transform::default())).id();
+
+        snapshot.restore(world).unwrap();
+
+        assert_eq!(world.get::<Transform>(one).unwrap().translation.x, 1.);
+        assert_eq!(world.get::<Transform>(two).unwrap().translation.x, 2.);
+        assert_eq!(PhysicsSnapshot::capture(world).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn rejects_broken_bytes() {
+        let mut first = app();
+        let world = first.world_mut();
+        world.spawn((StableId(1), Transform::default(), Velocity::default()));
+
+        let bytes = PhysicsSnapshot::capture(world).unwrap().to_bytes();
+
+        let truncated = PhysicsSnapshot::from_bytes(bytes[..bytes.len() - 1].to_vec());
+        assert_eq!(truncated.restore(world), Err(SnapshotError::Truncated));
+
+        world.spawn((StableId(1), Transform::default()));
+        assert_eq!(
+            PhysicsSnapshot::capture(world),
+            Err(SnapshotError::DuplicateId(1))
+        );
+
+        let mut other = app();
+        other.snapshot_component::<DropThrough>();
+        assert_eq!(
+            PhysicsSnapshot::from_bytes(bytes).restore(other.world_mut()),
+            Err(SnapshotError::LayoutMismatch)
+        );
+    }
+}
@@ -5,6 +5,10 @@ use super::*;
 #[derive(Component)]
 pub struct Solid;
 
+/// Solids with this component only block actors moving down onto them from above
+#[derive(Component, Default, Debug)]
+pub struct OneWay;
+
 #[derive(Bundle)]
 pub struct SolidBundle {
     pub solid: Solid,
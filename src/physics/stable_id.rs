@@ -0,0 +1,23 @@
+use bevy::prelude::*;
+use std::hash::Hasher;
+
+use super::*;
+
+/// Names an entity the same way in every run and on every peer
+///
+/// `Entity` ids depend on the order things were spawned in, which differs between peers and
+/// between the world a snapshot was taken in and the one it's restored into. Snapshots match
+/// entities up by this, and systems that need a deterministic order sort by it
+#[derive(Component, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[reflect(Component, Default)]
+pub struct StableId(pub u64);
+
+impl StableId {
+    /// Hashes a name that is unique within the game, like the iid of an LDtk entity
+    pub fn from_name(name: &str) -> Self {
+        let mut hasher = Fnv1a::new();
+        hasher.write(name.as_bytes());
+
+        Self(hasher.finish())
+    }
+}
@@ -1,6 +1,12 @@
 use bevy::prelude::*;
 
+/// Movement is accumulated in fractions of 1/SUBPIXELS of a pixel.
+/// Being a power of two, every quantized amount and remainder is exactly
+/// representable as an `f32`, so accumulation never drifts between runs
+pub const SUBPIXELS: f32 = 256.;
+
 #[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
 pub struct Velocity {
     pub value: Vec2,
     pub remainder: Vec2,
@@ -17,6 +23,17 @@ impl Velocity {
         dir
     }
 
+    /// Accumulates `value * delta` into the remainder and takes out the whole pixels to move
+    pub fn step(&mut self, delta: f32) -> IVec2 {
+        let amount = (self.value * delta * SUBPIXELS).round() / SUBPIXELS;
+
+        self.remainder += amount;
+        let amount_i = self.remainder.as_ivec2();
+        self.remainder -= amount_i.as_vec2();
+
+        amount_i
+    }
+
     #[inline]
     pub fn reset_x(&mut self) {
         self.value.x = 0.;
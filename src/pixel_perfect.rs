@@ -0,0 +1,161 @@
+use bevy::{
+    prelude::*,
+    render::{
+        camera::{extract_cameras, RenderTarget},
+        render_resource::{
+            Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+        view::{ExtractedView, RenderLayers},
+        RenderApp,
+    },
+    sprite::{ExtractedSprites, SpriteSystem},
+    window::{PrimaryWindow, WindowResized},
+};
+
+use crate::systems::{HEIGHT, WIDTH};
+
+/// Layer of the upscaled canvas, the game itself renders on the default layer
+const CANVAS_LAYER: usize = 1;
+
+/// The `WIDTH × HEIGHT` image the game camera renders to
+#[derive(Resource, Debug, Clone)]
+pub struct LowResTarget(pub Handle<Image>);
+
+impl LowResTarget {
+    pub fn render_target(&self) -> RenderTarget {
+        RenderTarget::Image(self.0.clone())
+    }
+}
+
+/// The camera drawing the low res image to the window
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct UpscaleCamera;
+
+/// The largest integer scale at which the low res image fits a window of `physical_size`
+pub fn integer_scale(physical_size: UVec2) -> u32 {
+    let scale = physical_size.as_vec2() / Vec2::new(WIDTH, HEIGHT);
+
+    (scale.x.min(scale.y).floor() as u32).max(1)
+}
+
+pub fn setup_low_res_target(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let size = Extent3d {
+        width: WIDTH as u32,
+        height: HEIGHT as u32,
+        ..default()
+    };
+
+    let mut canvas = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("low_res_target"),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+
+    // fills the image with zeroes
+    canvas.resize(size);
+
+    let canvas = images.add(canvas);
+
+    commands.spawn((
+        Name::new("Canvas"),
+        SpriteBundle {
+            texture: canvas.clone(),
+            ..default()
+        },
+        RenderLayers::layer(CANVAS_LAYER),
+    ));
+
+    commands.spawn((
+        Name::new("UpscaleCamera"),
+        Camera2dBundle {
+            camera: Camera {
+                // after the game camera has filled the canvas
+                order: 1,
+                // the bars around the canvas
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+            ..default()
+        },
+        RenderLayers::layer(CANVAS_LAYER),
+        UpscaleCamera,
+    ));
+
+    commands.insert_resource(LowResTarget(canvas));
+}
+
+/// Scales the canvas up by a whole number so every game pixel covers the same number of
+/// screen pixels, whatever is left of the window stays black
+pub fn fit_canvas(
+    mut ev_resized: EventReader<WindowResized>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<&mut OrthographicProjection, With<UpscaleCamera>>,
+    added: Query<(), Added<UpscaleCamera>>,
+) {
+    if ev_resized.read().last().is_none() && added.is_empty() {
+        return;
+    }
+
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+
+    let scale = integer_scale(window.physical_size());
+
+    for mut projection in &mut cameras {
+        // the projection works in logical pixels
+        projection.scale = window.scale_factor() / scale as f32;
+    }
+}
+
+fn round_translation(transform: &mut GlobalTransform) {
+    let mut affine = transform.affine();
+    affine.translation.x = affine.translation.x.round();
+    affine.translation.y = affine.translation.y.round();
+
+    *transform = GlobalTransform::from(affine);
+}
+
+/// Rounds the sprites and cameras extracted for rendering to whole pixels
+///
+/// Only the render world's copies are snapped. Physics reads the main world `GlobalTransform`s
+/// and smoothed movement like the camera's would get stuck on rounded positions
+pub fn snap_to_pixels(mut sprites: ResMut<ExtractedSprites>, mut views: Query<&mut ExtractedView>) {
+    for sprite in sprites.sprites.values_mut() {
+        round_translation(&mut sprite.transform);
+    }
+
+    for mut view in &mut views {
+        round_translation(&mut view.world_from_view);
+    }
+}
+
+pub struct PixelPerfectPlugin;
+
+impl Plugin for PixelPerfectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreStartup, setup_low_res_target)
+            .add_systems(Update, fit_canvas);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app.add_systems(
+            ExtractSchedule,
+            snap_to_pixels
+                .after(SpriteSystem::ExtractSprites)
+                .after(extract_cameras),
+        );
+    }
+}
@@ -1,112 +1,43 @@
-use bevy::{
-    math::{
-        bounding::{Aabb2d, IntersectsVolume},
-        vec2,
-    },
-    prelude::*,
-};
+use bevy::{math::vec2, prelude::*};
 use bevy_inspector_egui::prelude::*;
 
-use crate::physics::*;
+use crate::{
+    combat::{Hurtbox, Team},
+    input::{Action, ActionState},
+    physics::*,
+    tiles::{Climbable, Ice, Water},
+};
 
 const VELOCITY: f32 = 150.;
 const ACC: f32 = 1000.;
 const GRAVITY: f32 = 1000.;
 const FALL_VELOCITY: f32 = -400.;
 const JUMP_VELOCITY: f32 = 250.;
-
-#[derive(Clone, Copy, Debug)]
-pub struct PlayerCollider {
-    pub n: RayCast,
-    pub ne: RayCast,
-    pub se: RayCast,
-    pub s: RayCast,
-    pub sw: RayCast,
-    pub nw: RayCast,
-}
-
-impl PlayerCollider {
+const CLIMB_VELOCITY: f32 = 60.;
+const COYOTE_TIME: f32 = 0.08;
+// fraction of the acceleration left when standing on ice
+const ICE_GRIP: f32 = 0.25;
+// fraction of the gravity and fall speed left when in water
+const WATER_DRAG: f32 = 0.4;
+// how far below the player to look for a climbable when standing on top of one
+const CLIMB_PROBE: Vec2 = vec2(0., -2.);
+// about a second at the fixed rate
+const INVULNERABILITY_TICKS: u32 = 96;
+
+/// Rays reaching out from the player's center in every direction, the side rays are doubled
+/// at the top and bottom half
+pub fn player_collider() -> Collider {
     const NORTH_OFFSET: Vec2 = vec2(0., 4.);
     const SOUTH_OFFSET: Vec2 = vec2(0., -4.);
 
-    pub fn new() -> Self {
-        Self {
-            n: RayCast::new(Cardinal::North, 8.),
-            ne: RayCast::new(Cardinal::East, 4.),
-            se: RayCast::new(Cardinal::East, 4.),
-            s: RayCast::new(Cardinal::South, 8.),
-            sw: RayCast::new(Cardinal::West, 4.),
-            nw: RayCast::new(Cardinal::West, 4.),
-        }
-    }
-}
-
-impl Shape for PlayerCollider {
-    fn collides(&self, position: Vec2, aabb: &Aabb2d) -> bool {
-        self.n.collides(position, aabb)
-            || self.ne.collides(position + Self::NORTH_OFFSET, aabb)
-            || self.se.collides(position + Self::SOUTH_OFFSET, aabb)
-            || self.s.collides(position, aabb)
-            || self.sw.collides(position + Self::SOUTH_OFFSET, aabb)
-            || self.nw.collides(position + Self::NORTH_OFFSET, aabb)
-    }
-
-    fn get_collision_side(&self, position: Vec2, aabb: &Aabb2d) -> Option<Cardinal> {
-        if self.n.ray_cast(position).intersects(aabb) {
-            return Some(Cardinal::North);
-        }
-
-        if self
-            .ne
-            .ray_cast(position + Self::SOUTH_OFFSET)
-            .intersects(aabb)
-        {
-            return Some(Cardinal::East);
-        }
-
-        if self
-            .se
-            .ray_cast(position + Self::NORTH_OFFSET)
-            .intersects(aabb)
-        {
-            return Some(Cardinal::East);
-        }
-
-        if self.s.ray_cast(position).intersects(aabb) {
-            return Some(Cardinal::South);
-        }
-
-        if self
-            .sw
-            .ray_cast(position + Self::SOUTH_OFFSET)
-            .intersects(aabb)
-        {
-            return Some(Cardinal::West);
-        }
-
-        if self
-            .nw
-            .ray_cast(position + Self::NORTH_OFFSET)
-            .intersects(aabb)
-        {
-            return Some(Cardinal::West);
-        }
-
-        None
-    }
-
-    fn draw_gizmo(&self, gizmos: &mut Gizmos, position: Vec2, color: Color) {
-        self.n.draw_gizmo(gizmos, position, color);
-        self.ne
-            .draw_gizmo(gizmos, position + Self::NORTH_OFFSET, color);
-        self.se
-            .draw_gizmo(gizmos, position + Self::SOUTH_OFFSET, color);
-        self.s.draw_gizmo(gizmos, position, color);
-        self.sw
-            .draw_gizmo(gizmos, position + Self::SOUTH_OFFSET, color);
-        self.nw
-            .draw_gizmo(gizmos, position + Self::NORTH_OFFSET, color);
-    }
+    Collider::compound([
+        (Vec2::ZERO, SharedShape::ray_cast(Cardinal::North, 8.)),
+        (NORTH_OFFSET, SharedShape::ray_cast(Cardinal::East, 4.)),
+        (SOUTH_OFFSET, SharedShape::ray_cast(Cardinal::East, 4.)),
+        (Vec2::ZERO, SharedShape::ray_cast(Cardinal::South, 8.)),
+        (SOUTH_OFFSET, SharedShape::ray_cast(Cardinal::West, 4.)),
+        (NORTH_OFFSET, SharedShape::ray_cast(Cardinal::West, 4.)),
+    ])
 }
 
 #[derive(Component, Reflect, InspectorOptions)]
@@ -117,7 +48,11 @@ pub struct Player {
     gravity: f32,
     max_fall_speed: f32,
     jump_speed: f32,
+    climb_speed: f32,
     pub grounded: bool,
+    pub climbing: bool,
+    pub on_ice: bool,
+    pub in_water: bool,
 }
 
 impl Default for Player {
@@ -128,7 +63,11 @@ impl Default for Player {
             gravity: GRAVITY,
             max_fall_speed: FALL_VELOCITY,
             jump_speed: JUMP_VELOCITY,
+            climb_speed: CLIMB_VELOCITY,
             grounded: false,
+            climbing: false,
+            on_ice: false,
+            in_water: false,
         }
     }
 }
@@ -139,29 +78,29 @@ pub struct PlayerBundle {
     sprite: Sprite,
     texture: Handle<Image>,
     actor: ActorBundle,
+    corner_correction: CornerCorrection,
+    coyote_time: CoyoteTime,
+    hurtbox: Hurtbox,
+    team: Team,
+    stable_id: StableId,
 }
 
 impl PlayerBundle {
-    pub fn new(texture: Handle<Image>) -> Self {
-        let transform = Transform::from_xyz(20., 60., 0.);
-
+    pub fn new(texture: Handle<Image>, position: Vec2) -> Self {
         Self {
             texture,
-            actor: ActorBundle::new(
-                transform.translation.xy(),
-                Collider::custom(PlayerCollider::new()),
-            ),
+            actor: ActorBundle::new(position, player_collider()),
+            coyote_time: CoyoteTime::new(COYOTE_TIME),
+            // the area the rays reach out to
+            hurtbox: Hurtbox::new(SharedShape::aabb(vec2(4., 8.)), INVULNERABILITY_TICKS),
+            team: Team::Player,
+            // there is only ever one player
+            stable_id: StableId::from_name("player"),
             ..default()
         }
     }
 }
 
-pub fn get_input_axis(keys: &ButtonInput<KeyCode>, pos: KeyCode, neg: KeyCode) -> f32 {
-    let pos = if keys.pressed(pos) { 1. } else { 0. };
-    let neg = if keys.pressed(neg) { 1. } else { 0. };
-    pos - neg
-}
-
 pub fn approach(value: f32, target: f32, delta: f32) -> f32 {
     if value > target {
         target.max(value - delta)
@@ -171,46 +110,104 @@ pub fn approach(value: f32, target: f32, delta: f32) -> f32 {
 }
 
 pub fn handle_input(
-    time: Res<Time>,
-    keys: Res<ButtonInput<KeyCode>>,
-    mut player: Query<(&mut Velocity, &Player)>,
+    time: Res<Time<Fixed>>,
+    actions: Res<ActionState>,
+    mut player: Query<(&mut Velocity, &mut Player, &mut CoyoteTime)>,
 ) {
-    if keys.pressed(KeyCode::KeyQ) {
-        std::process::Command::new("clear").status().unwrap();
-    }
-
-    let Ok((mut velocity, player)) = player.get_single_mut() else {
+    let Ok((mut velocity, mut player, mut coyote_time)) = player.get_single_mut() else {
         return;
     };
 
-    let delta = time.delta_seconds();
+    let delta = time.timestep().as_secs_f32();
+
+    if player.climbing {
+        velocity.reset_x();
+
+        if actions.just_pressed(Action::Jump) {
+            player.climbing = false;
+            velocity.value.y = player.jump_speed;
+        } else {
+            let y_axis = actions.value(Action::Up) - actions.value(Action::Down);
+            velocity.value.y = player.climb_speed * y_axis;
+        }
 
-    let x_axis = get_input_axis(&keys, KeyCode::ArrowRight, KeyCode::ArrowLeft);
+        return;
+    }
 
-    // let y_axis = get_input_axis(&keys, KeyCode::ArrowUp, KeyCode::ArrowDown);
-    // velocity.value = Vec2::splat(75.) * Vec2::new(x_axis, y_axis);
+    let x_axis = actions.value(Action::Move);
+    let grip = if player.on_ice { ICE_GRIP } else { 1. };
+    let drag = if player.in_water { WATER_DRAG } else { 1. };
 
     velocity.value.x = approach(
         velocity.value.x,
         player.max_speed * x_axis,
-        player.acceleration * delta,
+        player.acceleration * grip * delta,
     );
     velocity.value.y = approach(
         velocity.value.y,
-        player.max_fall_speed,
-        player.gravity * delta,
+        player.max_fall_speed * drag,
+        player.gravity * drag * delta,
     );
 
-    if player.grounded && keys.just_pressed(KeyCode::KeyC) {
+    if (player.grounded || coyote_time.is_active()) && actions.just_pressed(Action::Jump) {
         velocity.value.y = player.jump_speed;
+        coyote_time.consume();
+    }
+}
+
+pub fn update_player_climbing(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    mut players: Query<(
+        Entity,
+        &mut Player,
+        &Collider,
+        &Velocity,
+        &GlobalTransform,
+        Option<&ColliderOffset>,
+        Has<DropThrough>,
+    )>,
+    climbables: Query<(&Collider, &GlobalTransform, Option<&ColliderOffset>), With<Climbable>>,
+) {
+    for (entity, mut player, collider, velocity, transform, offset, drop_through) in &mut players {
+        let position = collider_position(transform, offset);
+        let on_climbable = overlaps_any(collider, position, &climbables);
+
+        player.climbing = if player.climbing {
+            on_climbable
+        } else {
+            // grab while standing or falling, not while still rising from a jump
+            (on_climbable && actions.pressed(Action::Up) && velocity.value.y <= 0.)
+                || (actions.pressed(Action::Down)
+                    && overlaps_any(collider, position + CLIMB_PROBE, &climbables))
+        };
+
+        // climbing down from the top has to pass through the one way top
+        if player.climbing && !drop_through {
+            commands.entity(entity).insert(DropThrough);
+        } else if !player.climbing && drop_through {
+            commands.entity(entity).remove::<DropThrough>();
+        }
     }
 }
 
 pub fn handle_collision(
     mut ev_collision: EventReader<CollisionEvent>,
-    mut player: Query<(Entity, &Collider, &mut Velocity, &mut Transform)>,
+    mut player: Query<
+        (
+            Entity,
+            &Collider,
+            &mut Velocity,
+            &mut Transform,
+            &mut GlobalTransform,
+            Option<&ColliderOffset>,
+        ),
+        With<Player>,
+    >,
 ) {
-    let Ok((entity, collider, mut velocity, mut transform)) = player.get_single_mut() else {
+    let Ok((entity, collider, mut velocity, mut transform, mut global_transform, offset)) =
+        player.get_single_mut()
+    else {
         return;
     };
 
@@ -219,34 +216,31 @@ pub fn handle_collision(
             continue;
         }
 
-        let TypedShape::Custom(p) = collider.as_typed_shape() else {
+        let position = collider_position(&global_transform, offset);
+
+        let Some(collision_side) =
+            collider.get_collision_side(position + ev.direction.as_vec2(), &ev.solid)
+        else {
             continue;
         };
 
-        let Some(collision_side) = p.get_collision_side(
-            transform.translation.xy() + ev.direction.as_vec2(),
-            &ev.solid,
-        ) else {
+        let Some(reach) = collider.reach(collision_side) else {
             continue;
         };
 
+        // how far the collider has to move to sit flush against the solid
+        let snap = match collision_side {
+            Cardinal::North => vec2(0., ev.solid.min.y - reach - position.y),
+            Cardinal::East => vec2(ev.solid.min.x - reach - position.x, 0.),
+            Cardinal::South => vec2(0., ev.solid.max.y + reach - position.y),
+            Cardinal::West => vec2(ev.solid.max.x + reach - position.x, 0.),
+        };
+
+        translate_collider(&mut transform, &mut global_transform, snap);
+
         match collision_side {
-            Cardinal::North => {
-                transform.translation.y = ev.solid.min.y - 8.;
-                velocity.reset_y();
-            }
-            Cardinal::East => {
-                transform.translation.x = ev.solid.min.x - 4.;
-                velocity.reset_x();
-            }
-            Cardinal::South => {
-                transform.translation.y = ev.solid.max.y + 8.;
-                velocity.reset_y();
-            }
-            Cardinal::West => {
-                transform.translation.x = ev.solid.max.x + 4.;
-                velocity.reset_x();
-            }
+            Cardinal::North | Cardinal::South => velocity.reset_y(),
+            Cardinal::East | Cardinal::West => velocity.reset_x(),
         }
 
         // handle one event at a time
@@ -255,22 +249,76 @@ pub fn handle_collision(
 }
 
 pub fn update_player_grounded(
-    mut actor: Query<(&mut Player, &Collider, &Transform)>,
-    solids: Query<(&Collider, &Transform), (With<Solid>, Without<Player>)>,
+    mut actor: Query<(
+        &mut Player,
+        &Collider,
+        &GlobalTransform,
+        Option<&ColliderOffset>,
+    )>,
+    solids: Query<
+        (
+            &Collider,
+            &GlobalTransform,
+            Option<&ColliderOffset>,
+            Has<OneWay>,
+            Has<Ice>,
+        ),
+        (With<Solid>, Without<Player>),
+    >,
+    water: Query<(&Collider, &GlobalTransform, Option<&ColliderOffset>), With<Water>>,
 ) {
-    for (mut player, collider, transform) in &mut actor {
-        player.grounded = solids.iter().any(|(solid, solid_transform)| {
-            let TypedShape::Aabb(solid) = solid.as_typed_shape() else {
-                return false;
-            };
-
-            matches!(
-                collider.get_collision_side(
-                    transform.translation.xy(),
-                    &solid.aabb(solid_transform.translation.xy())
-                ),
-                Some(Cardinal::South)
-            )
-        });
+    for (mut player, collider, transform, offset) in &mut actor {
+        let position = collider_position(transform, offset);
+
+        let ground = solids
+            .iter()
+            .filter(|(solid, solid_transform, solid_offset, one_way, _)| {
+                let TypedShape::Aabb(solid) = solid.as_typed_shape() else {
+                    return false;
+                };
+
+                let solid = solid.aabb(collider_position(solid_transform, *solid_offset));
+
+                // passing through a one way solid doesn't count as standing on it
+                if *one_way && collider.collides(position, &solid) {
+                    return false;
+                }
+
+                matches!(
+                    collider.get_collision_side(position, &solid),
+                    Some(Cardinal::South)
+                )
+            })
+            .map(|(.., ice)| ice)
+            .collect::<Vec<_>>();
+
+        player.grounded = !ground.is_empty();
+        // only slide when there is nothing but ice underneath
+        player.on_ice = player.grounded && ground.iter().all(|ice| *ice);
+        player.in_water = overlaps_any(collider, position, &water);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn player_collider_reaches_as_far_as_its_rays() {
+        let collider = player_collider();
+
+        assert_eq!(collider.reach(Cardinal::North), Some(8.));
+        assert_eq!(collider.reach(Cardinal::South), Some(8.));
+        assert_eq!(collider.reach(Cardinal::East), Some(4.));
+        assert_eq!(collider.reach(Cardinal::West), Some(4.));
+
+        let offset_box = Collider::compound([
+            (vec2(2., 0.), SharedShape::aabb(vec2(4., 3.))),
+            (vec2(0., 1.), SharedShape::ball(2.)),
+        ]);
+
+        assert_eq!(offset_box.reach(Cardinal::East), Some(6.));
+        assert_eq!(offset_box.reach(Cardinal::West), Some(2.));
+        assert_eq!(offset_box.reach(Cardinal::North), Some(3.));
     }
 }
@@ -0,0 +1,233 @@
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    hash::Hasher,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    input::{Action, ActionState, ActionSystem},
+    levels::level_ready,
+    physics::{Actor, Fnv1a, Velocity},
+};
+
+/// What happens with the per-tick player actions
+///
+/// Selected from the command line with `--record <file>` or `--replay <file>`
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub enum ReplayMode {
+    #[default]
+    Off,
+    Recording(PathBuf),
+    Replaying(PathBuf),
+}
+
+impl ReplayMode {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        while let Some(arg) = args.next() {
+            match (arg.as_str(), args.next()) {
+                ("--record", Some(path)) => return Self::Recording(path.into()),
+                ("--replay", Some(path)) => return Self::Replaying(path.into()),
+                _ => (),
+            }
+        }
+
+        Self::Off
+    }
+}
+
+/// The actions and resulting physics checksum of a single fixed tick
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub actions: BTreeMap<Action, f32>,
+    pub checksum: u64,
+}
+
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Replay {
+    pub frames: Vec<ReplayFrame>,
+    /// Index of the tick being recorded or replayed
+    #[serde(skip)]
+    pub cursor: usize,
+    /// First tick whose checksum didn't match the recording
+    #[serde(skip)]
+    pub diverged_at: Option<usize>,
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        ron::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let contents =
+            ron::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, contents)
+    }
+}
+
+/// Order independent hash of the position and velocity of every actor
+pub fn actor_checksum<'a>(actors: impl Iterator<Item = (&'a Transform, &'a Velocity)>) -> u64 {
+    actors
+        .map(|(transform, velocity)| {
+            let mut hasher = Fnv1a::new();
+
+            for value in [
+                transform.translation.x,
+                transform.translation.y,
+                velocity.value.x,
+                velocity.value.y,
+                velocity.remainder.x,
+                velocity.remainder.y,
+            ] {
+                hasher.write(&value.to_bits().to_le_bytes());
+            }
+
+            hasher.finish()
+        })
+        .fold(0, u64::wrapping_add)
+}
+
+pub fn load_replay(mut commands: Commands, mut mode: ResMut<ReplayMode>) {
+    let ReplayMode::Replaying(path) = mode.as_ref() else {
+        return;
+    };
+
+    match Replay::load(path) {
+        Ok(replay) => {
+            info!(
+                "replaying {} ticks from {}",
+                replay.frames.len(),
+                path.display()
+            );
+            commands.insert_resource(replay);
+        }
+        Err(e) => {
+            error!("could not load replay {}: {e}", path.display());
+            *mode = ReplayMode::Off;
+        }
+    }
+}
+
+pub fn record_actions(actions: Res<ActionState>, mut replay: ResMut<Replay>) {
+    replay.frames.push(ReplayFrame {
+        actions: Action::ALL
+            .into_iter()
+            .map(|action| (action, actions.value(action)))
+            .collect(),
+        checksum: 0,
+    });
+}
+
+pub fn feed_replay_actions(mut actions: ResMut<ActionState>, replay: Res<Replay>) {
+    let Some(frame) = replay.frames.get(replay.cursor) else {
+        return;
+    };
+
+    actions.clear();
+
+    for action in Action::ALL {
+        actions.set(action, frame.actions.get(&action).copied().unwrap_or(0.));
+    }
+}
+
+pub fn record_checksum(
+    actors: Query<(&Transform, &Velocity), With<Actor>>,
+    mut replay: ResMut<Replay>,
+) {
+    let cursor = replay.cursor;
+
+    if let Some(frame) = replay.frames.get_mut(cursor) {
+        frame.checksum = actor_checksum(actors.iter());
+    }
+
+    replay.cursor += 1;
+}
+
+pub fn verify_checksum(
+    actors: Query<(&Transform, &Velocity), With<Actor>>,
+    mut replay: ResMut<Replay>,
+    mut mode: ResMut<ReplayMode>,
+) {
+    let Some(expected) = replay.frames.get(replay.cursor).map(|frame| frame.checksum) else {
+        info!("replay finished after {} ticks", replay.cursor);
+        *mode = ReplayMode::Off;
+        return;
+    };
+
+    let found = actor_checksum(actors.iter());
+
+    if found != expected && replay.diverged_at.is_none() {
+        warn!(
+            "replay diverged at tick {}: expected checksum {expected:016x}, found {found:016x}",
+            replay.cursor
+        );
+
+        replay.diverged_at = Some(replay.cursor);
+    }
+
+    replay.cursor += 1;
+}
+
+pub fn save_recording(
+    mut ev_exit: EventReader<AppExit>,
+    mode: Res<ReplayMode>,
+    replay: Res<Replay>,
+) {
+    let ReplayMode::Recording(path) = mode.as_ref() else {
+        return;
+    };
+
+    if ev_exit.read().next().is_none() {
+        return;
+    }
+
+    match replay.save(path) {
+        Ok(()) => info!("saved {} ticks to {}", replay.frames.len(), path.display()),
+        Err(e) => error!("could not save replay {}: {e}", path.display()),
+    }
+}
+
+fn recording(mode: Res<ReplayMode>) -> bool {
+    matches!(*mode, ReplayMode::Recording(_))
+}
+
+fn replaying(mode: Res<ReplayMode>) -> bool {
+    matches!(*mode, ReplayMode::Replaying(_))
+}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        // ticks only count while there is something to simulate, so a replay
+        // lines up with its recording no matter how long levels took to load
+        let simulating = || any_with_component::<Actor>.and_then(level_ready);
+
+        app.insert_resource(ReplayMode::from_args(std::env::args().skip(1)))
+            .init_resource::<Replay>()
+            .add_systems(PreStartup, load_replay)
+            // while replaying the recorded actions replace the devices entirely
+            .configure_sets(FixedPreUpdate, ActionSystem.run_if(not(replaying)))
+            .add_systems(
+                FixedPreUpdate,
+                (
+                    record_actions.run_if(recording.and_then(simulating())),
+                    feed_replay_actions.run_if(replaying.and_then(simulating())),
+                )
+                    .after(ActionSystem),
+            )
+            .add_systems(
+                FixedPostUpdate,
+                (
+                    record_checksum.run_if(recording.and_then(simulating())),
+                    verify_checksum.run_if(replaying.and_then(simulating())),
+                ),
+            )
+            .add_systems(Last, save_recording.run_if(recording));
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::{
+    input::{Action, ActionState, ActionSystem},
+    levels::level_ready,
+    physics::{Actor, Physics, PhysicsSnapshot},
+    replay::feed_replay_actions,
+};
+
+/// How many ticks can be rewound, a few seconds at the fixed rate
+const HISTORY_TICKS: usize = 480;
+
+/// A snapshot of the physics state after every simulated tick, oldest first
+#[derive(Resource, Debug, Clone)]
+pub struct RewindHistory {
+    pub ticks: VecDeque<PhysicsSnapshot>,
+    pub capacity: usize,
+}
+
+impl Default for RewindHistory {
+    fn default() -> Self {
+        Self {
+            ticks: VecDeque::with_capacity(HISTORY_TICKS),
+            capacity: HISTORY_TICKS,
+        }
+    }
+}
+
+impl RewindHistory {
+    pub fn push(&mut self, snapshot: PhysicsSnapshot) {
+        while self.ticks.len() >= self.capacity.max(1) {
+            self.ticks.pop_front();
+        }
+
+        self.ticks.push_back(snapshot);
+    }
+
+    /// Drops the latest tick, which is the state the world is already in, and returns the one
+    /// before it, the oldest tick is kept so rewinding stops there
+    pub fn step_back(&mut self) -> Option<PhysicsSnapshot> {
+        if self.ticks.len() > 1 {
+            self.ticks.pop_back();
+        }
+
+        self.ticks.back().cloned()
+    }
+}
+
+/// Whether the world is being played backwards this tick
+///
+/// Nothing is simulated while rewinding, once the input is released the simulation
+/// picks up from whichever tick was restored last
+#[derive(Resource, Debug, Default, PartialEq, Eq)]
+pub struct Rewinding(pub bool);
+
+pub fn rewinding(rewinding: Res<Rewinding>) -> bool {
+    rewinding.0
+}
+
+pub fn rewind_history(world: &mut World) {
+    let active = world.resource::<ActionState>().pressed(Action::Rewind);
+
+    let snapshot = active
+        .then(|| world.resource_mut::<RewindHistory>().step_back())
+        .flatten();
+
+    if let Some(snapshot) = &snapshot {
+        if let Err(e) = snapshot.restore(world) {
+            error!("could not rewind: {e}");
+        }
+    }
+
+    world
+        .resource_mut::<Rewinding>()
+        .set_if_neq(Rewinding(snapshot.is_some()));
+}
+
+pub fn record_history(world: &mut World) {
+    match PhysicsSnapshot::capture(world) {
+        Ok(snapshot) => world.resource_mut::<RewindHistory>().push(snapshot),
+        Err(e) => error!("could not record rewind history: {e}"),
+    }
+}
+
+pub struct RewindPlugin;
+
+impl Plugin for RewindPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RewindHistory>()
+            .init_resource::<Rewinding>()
+            .configure_sets(FixedUpdate, Physics::Simulation.run_if(not(rewinding)))
+            .add_systems(
+                FixedPreUpdate,
+                // replays feed the rewind input like any other action
+                rewind_history
+                    .after(ActionSystem)
+                    .after(feed_replay_actions),
+            )
+            .add_systems(
+                FixedPostUpdate,
+                record_history.run_if(
+                    not(rewinding)
+                        .and_then(any_with_component::<Actor>)
+                        .and_then(level_ready),
+                ),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(n: u8) -> PhysicsSnapshot {
+        PhysicsSnapshot::from_bytes(vec![n])
+    }
+
+    #[test]
+    fn steps_back_one_tick_at_a_time() {
+        let mut history = RewindHistory::default();
+
+        for n in 0..=4 {
+            history.push(tick(n));
+        }
+
+        // the world is at tick 4, so one step back restores tick 3
+        assert_eq!(history.step_back(), Some(tick(3)));
+        assert_eq!(history.step_back(), Some(tick(2)));
+        assert_eq!(history.step_back(), Some(tick(1)));
+        assert_eq!(history.step_back(), Some(tick(0)));
+        assert_eq!(history.step_back(), Some(tick(0)));
+        assert_eq!(history.ticks.len(), 1);
+
+        assert_eq!(RewindHistory::default().step_back(), None);
+    }
+}
@@ -0,0 +1,373 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::{
+    input::ActionState,
+    levels::level_ready,
+    physics::{Actor, PhysicsCounters, PhysicsSnapshot, SnapshotError, StepTrace},
+    rewind::{record_history, rewind_history, Rewinding},
+};
+
+/// Rolls the world back every tick and simulates it forward again, the way a rollback
+/// session does when a late remote input arrives
+///
+/// Both runs have to end in exactly the same state, otherwise peers would drift apart.
+/// Enabled from the command line with `--synctest <ticks>`
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncTest {
+    /// How many ticks are rolled back and simulated again
+    pub distance: usize,
+}
+
+impl SyncTest {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        while let Some(arg) = args.next() {
+            if arg == "--synctest" {
+                let distance = args.next().and_then(|ticks| ticks.parse().ok())?;
+                return (distance > 0).then_some(Self { distance });
+            }
+        }
+
+        None
+    }
+}
+
+/// What is needed to simulate a tick again: the state before it and its actions
+#[derive(Debug, Clone)]
+pub struct RollbackFrame {
+    pub before: PhysicsSnapshot,
+    pub actions: ActionState,
+}
+
+/// The last `SyncTest::distance` ticks, oldest first
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RollbackFrames {
+    pub frames: VecDeque<RollbackFrame>,
+    /// Ticks whose resimulation ended in a different state
+    pub mismatches: usize,
+}
+
+pub fn save_rollback_frame(world: &mut World) {
+    // restoring history isn't a tick that can be simulated again
+    if world.resource::<Rewinding>().0 {
+        world.resource_mut::<RollbackFrames>().frames.clear();
+        return;
+    }
+
+    let before = match PhysicsSnapshot::capture(world) {
+        Ok(before) => before,
+        Err(e) => {
+            error!("could not save rollback frame: {e}");
+            return;
+        }
+    };
+    let actions = world.resource::<ActionState>().clone();
+
+    world
+        .resource_mut::<RollbackFrames>()
+        .frames
+        .push_back(RollbackFrame { before, actions });
+}
+
+pub fn clear_rollback_frames(mut rollback: ResMut<RollbackFrames>) {
+    rollback.frames.clear();
+}
+
+/// Restores the oldest saved frame and runs `FixedFirst` and `FixedUpdate` once per frame
+/// since then
+pub fn check_rollback(world: &mut World) {
+    let distance = world.resource::<SyncTest>().distance;
+
+    let mut frames = std::mem::take(&mut world.resource_mut::<RollbackFrames>().frames);
+
+    while frames.len() > distance {
+        frames.pop_front();
+    }
+
+    if frames.len() == distance {
+        if let Err(e) = resimulate(world, &frames) {
+            error!("could not roll back: {e}");
+        }
+    }
+
+    world.resource_mut::<RollbackFrames>().frames = frames;
+}
+
+fn resimulate(world: &mut World, frames: &VecDeque<RollbackFrame>) -> Result<(), SnapshotError> {
+    let Some(oldest) = frames.front() else {
+        return Ok(());
+    };
+
+    let expected = PhysicsSnapshot::capture(world)?;
+    let actions = world.resource::<ActionState>().clone();
+
+    // the work done again isn't counted or traced, the diagnostics and debug gizmos show the
+    // ticks that were actually played
+    let counters = std::mem::take(&mut *world.resource_mut::<PhysicsCounters>());
+    let trace = world.remove_resource::<StepTrace>();
+
+    let resimulated = resimulate_frames(world, oldest, frames);
+
+    world.insert_resource(actions);
+    *world.resource_mut::<PhysicsCounters>() = counters;
+
+    if let Some(trace) = trace {
+        world.insert_resource(trace);
+    }
+
+    resimulated?;
+
+    let found = PhysicsSnapshot::capture(world)?;
+
+    if found != expected {
+        let mut rollback = world.resource_mut::<RollbackFrames>();
+        rollback.mismatches += 1;
+
+        warn!(
+            "rolling back {} ticks diverged ({} times so far)",
+            frames.len(),
+            rollback.mismatches
+        );
+
+        // carry on from what actually happened so one mismatch isn't reported every tick
+        expected.restore(world)?;
+    }
+
+    Ok(())
+}
+
+fn resimulate_frames(
+    world: &mut World,
+    oldest: &RollbackFrame,
+    frames: &VecDeque<RollbackFrame>,
+) -> Result<(), SnapshotError> {
+    oldest.before.restore(world)?;
+
+    for frame in frames {
+        world.insert_resource(frame.actions.clone());
+        // brings the restored transforms into world space before they are tested
+        world.run_schedule(FixedFirst);
+        world.run_schedule(FixedUpdate);
+    }
+
+    Ok(())
+}
+
+pub struct RollbackPlugin;
+
+impl Plugin for RollbackPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(sync_test) = SyncTest::from_args(std::env::args().skip(1)) else {
+            return;
+        };
+
+        info!("rolling back {} ticks every tick", sync_test.distance);
+
+        let simulating = || any_with_component::<Actor>.and_then(level_ready);
+
+        app.insert_resource(sync_test)
+            .init_resource::<RollbackFrames>()
+            .add_systems(
+                FixedPreUpdate,
+                (
+                    save_rollback_frame
+                        .after(rewind_history)
+                        .run_if(simulating()),
+                    // frames saved before a pause aren't the ticks right before the next one
+                    clear_rollback_frames.run_if(not(simulating())),
+                ),
+            )
+            .add_systems(
+                FixedPostUpdate,
+                check_rollback.before(record_history).run_if(simulating()),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::{
+            detect_triggers, move_platforms, patrol_enemies, Enemy, MovingPlatform, Trigger,
+            TriggerEvent,
+        },
+        input::Action,
+        physics::*,
+        rewind::Rewinding,
+    };
+    use bevy::transform::systems::{propagate_transforms, sync_simple_transforms};
+
+    #[derive(Component)]
+    struct Runner;
+
+    fn steer(actions: Res<ActionState>, mut runners: Query<&mut Velocity, With<Runner>>) {
+        for mut velocity in &mut runners {
+            velocity.value.x = actions.value(Action::Move) * 120.;
+            velocity.value.y = (velocity.value.y - 20.).max(-300.);
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.insert_resource(Time::<Fixed>::from_hz(96.))
+            .insert_resource(SyncTest { distance: 3 })
+            .init_resource::<RollbackFrames>()
+            .init_resource::<Rewinding>()
+            .init_resource::<ActionState>()
+            .init_resource::<PhysicsCounters>()
+            .add_event::<CollisionEvent>()
+            .add_event::<TriggerEvent>()
+            .snapshot_component::<Transform>()
+            .snapshot_component::<Velocity>()
+            .snapshot_component::<Contacts>()
+            .snapshot_component::<Enemy>()
+            .snapshot_component::<MovingPlatform>()
+            .snapshot_component::<Trigger>()
+            .add_systems(FixedFirst, (sync_simple_transforms, propagate_transforms))
+            .add_systems(
+                FixedUpdate,
+                (
+                    steer,
+                    patrol_enemies,
+                    move_platforms,
+                    simulate_actor_movement,
+                    detect_triggers,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    fn spawn_level(world: &mut World, reversed: bool) {
+        let mut spawns: Vec<Box<dyn FnOnce(&mut World)>> = vec![
+            Box::new(|world| {
+                world.spawn((
+                    StableId(1),
+                    SolidBundle::new(Vec2::new(0., -8.), Vec2::new(200., 8.)),
+                ));
+            }),
+            Box::new(|world| {
+                world.spawn((
+                    StableId(2),
+                    SolidBundle::new(Vec2::new(-208., 100.), Vec2::new(8., 100.)),
+                ));
+            }),
+            Box::new(|world| {
+                world.spawn((
+                    StableId(3),
+                    SolidBundle::new(Vec2::new(208., 100.), Vec2::new(8., 100.)),
+                ));
+            }),
+            Box::new(|world| {
+                world.spawn((
+                    StableId(4),
+                    MovingPlatform {
+                        path: vec![Vec2::ZERO, Vec2::new(48., 0.)],
+                        speed: 30.,
+                        ..default()
+                    },
+                    Velocity::default(),
+                    SolidBundle::new(Vec2::new(-120., 4.), Vec2::new(16., 4.)),
+                ));
+            }),
+            Box::new(|world| {
+                world.spawn((
+                    StableId(5),
+                    Enemy::default(),
+                    ActorBundle::new(Vec2::new(-170., 8.), Collider::aabb(Vec2::new(4., 8.))),
+                ));
+            }),
+            Box::new(|world| {
+                world.spawn((
+                    StableId(6),
+                    Runner,
+                    ActorBundle::new(Vec2::new(100., 8.), Collider::aabb(Vec2::new(4., 8.))),
+                ));
+            }),
+            Box::new(|world| {
+                world.spawn((
+                    StableId(7),
+                    Trigger::default(),
+                    AreaBundle::new(Vec2::new(40., 16.), Vec2::new(16., 16.)),
+                ));
+            }),
+        ];
+
+        if reversed {
+            // shifts every entity id, and with them the order queries iterate in
+            world.spawn_empty();
+            spawns.reverse();
+        }
+
+        for spawn in spawns {
+            spawn(world);
+        }
+    }
+
+    fn tick(world: &mut World, tick: u32, rolling_back: bool) {
+        // left, still and right for 40 ticks each
+        let direction = ((tick / 40) % 3) as f32 - 1.;
+        world
+            .resource_mut::<ActionState>()
+            .set(Action::Move, direction);
+
+        if rolling_back {
+            save_rollback_frame(world);
+        }
+
+        world.run_schedule(FixedFirst);
+        world.run_schedule(FixedUpdate);
+
+        if rolling_back {
+            check_rollback(world);
+        }
+    }
+
+    #[test]
+    fn rolling_back_matches_a_plain_run() {
+        let mut plain = app();
+        spawn_level(plain.world_mut(), false);
+
+        let mut rolled_back = app();
+        spawn_level(rolled_back.world_mut(), true);
+
+        let start = PhysicsSnapshot::capture(plain.world()).unwrap();
+        assert_eq!(
+            PhysicsSnapshot::capture(rolled_back.world()).unwrap(),
+            start
+        );
+
+        let mut triggered = false;
+        let mut turned = false;
+
+        for i in 0..240 {
+            tick(plain.world_mut(), i, false);
+            tick(rolled_back.world_mut(), i, true);
+
+            assert_eq!(
+                PhysicsSnapshot::capture(rolled_back.world()).unwrap(),
+                PhysicsSnapshot::capture(plain.world()).unwrap(),
+                "diverged on tick {i}"
+            );
+
+            let world = plain.world_mut();
+            triggered |= world
+                .query::<&Trigger>()
+                .iter(world)
+                .any(|trigger| !trigger.inside.is_empty());
+            turned |= world
+                .query::<&Enemy>()
+                .iter(world)
+                .any(|enemy| enemy.direction != Enemy::default().direction);
+        }
+
+        // the run has to get to the state that isn't plain transforms
+        assert!(triggered && turned);
+
+        assert_eq!(
+            rolled_back.world().resource::<RollbackFrames>().mismatches,
+            0
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use bevy::{prelude::*, render::camera::ScalingMode::FixedHorizontal};
 use bevy_ecs_ldtk::prelude::*;
+use bevy_ecs_tilemap::tiles::TilePos;
 use std::collections::{HashMap, HashSet};
 
-use crate::physics::*;
-use crate::player::PlayerBundle;
+use crate::camera::CameraFollow;
+use crate::pixel_perfect::LowResTarget;
+use crate::tiles::*;
 
 #[derive(Component)]
 pub struct CameraMarker;
@@ -11,9 +13,17 @@ pub struct CameraMarker;
 pub const WIDTH: f32 = 320.;
 pub const HEIGHT: f32 = 190.;
 
-pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    low_res_target: Res<LowResTarget>,
+) {
     commands.spawn((
         Camera2dBundle {
+            camera: Camera {
+                target: low_res_target.render_target(),
+                ..default()
+            },
             projection: OrthographicProjection {
                 near: -1000.0,
                 far: 1000.0,
@@ -24,6 +34,7 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             ..default()
         },
         CameraMarker,
+        CameraFollow::default(),
     ));
 
     let ldtk_handle = asset_server.load("level.ldtk");
@@ -42,171 +53,239 @@ pub struct TileBundle {
     tile: Tile,
 }
 
+/// Spawns merged colliders for new tiles and rebuilds them where tiles changed or were removed
 pub fn spawn_tile_collisions(
     mut commands: Commands,
-    tile_query: Query<(&GridCoords, &Parent), Added<Tile>>,
-    parent_query: Query<&Parent, Without<Tile>>,
-    level_query: Query<(Entity, &LevelIid)>,
-    asset_server: Res<AssetServer>,
+    tile_query: Query<
+        (Entity, &GridCoords, &IntGridCell, &Parent),
+        (With<Tile>, Or<(Changed<GridCoords>, Changed<IntGridCell>)>),
+    >,
+    tileset_query: Query<(
+        Entity,
+        &TilePos,
+        Option<&TileEnumTags>,
+        Option<&TileMetadata>,
+        &Parent,
+    )>,
+    changed_tileset_tiles: Query<
+        Entity,
+        (
+            With<TilePos>,
+            Or<(Changed<TileEnumTags>, Changed<TileMetadata>)>,
+        ),
+    >,
+    mut removed_tiles: RemovedComponents<Tile>,
+    mut removed_tileset_tiles: RemovedComponents<TilePos>,
+    mut removed_enum_tags: RemovedComponents<TileEnumTags>,
+    mut removed_metadata: RemovedComponents<TileMetadata>,
+    layer_query: Query<(&Parent, &LayerMetadata), Without<Tile>>,
+    level_query: Query<&Parent, With<LevelIid>>,
+    world_query: Query<&Handle<LdtkProject>>,
+    tile_colliders: Query<(Entity, &TileCollider, &Parent)>,
     ldtk_project_assets: Res<Assets<LdtkProject>>,
+    int_grid_colliders: Res<IntGridColliders>,
+    decomposition: Res<TileDecomposition>,
+    mut tile_index: ResMut<TileIndex>,
 ) {
-    if tile_query.is_empty() {
-        return;
+    // The region that needs rebuilding, for every level, cell size and collider kind
+    //
+    // Only cells of the same kind are merged, and keying by level entity
+    // 1. forces the tiles to be split along level boundaries
+    // 2. lets us easily add the collision entities as children of the appropriate level entity
+    let mut dirty: HashMap<(Entity, i32, ColliderKind), GridRect> = HashMap::new();
+
+    // switching decompositions rebuilds everything
+    if decomposition.is_changed() && !decomposition.is_added() {
+        for (&(level, grid_size), level_tiles) in &tile_index.levels {
+            let whole_level = GridRect {
+                left: 0,
+                right: level_tiles.width - 1,
+                top: level_tiles.height - 1,
+                bottom: 0,
+            };
+
+            for &kind in level_tiles.cells.keys() {
+                dirty.insert((level, grid_size, kind), whole_level);
+            }
+        }
     }
 
-    /// Represents a wide wall that is 1 tile tall
-    /// Used to spawn tile collisions
-    #[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
-    struct Plate {
-        left: i32,
-        right: i32,
+    let mut mark_dirty = |tile: IndexedTile| {
+        let cell = GridRect::cell(tile.coords);
+
+        dirty
+            .entry((tile.level, tile.grid_size, tile.kind))
+            .and_modify(|region| *region = region.union(&cell))
+            .or_insert(cell);
+    };
+
+    for tile in removed_tiles.read() {
+        if let Some(indexed) = tile_index.remove(tile, TileSource::IntGrid) {
+            mark_dirty(indexed);
+        }
     }
 
-    /// A simple rectangle type representing a wall of any size
-    struct Rect {
-        left: i32,
-        right: i32,
-        top: i32,
-        bottom: i32,
+    for tile in removed_tileset_tiles.read() {
+        for source in [TileSource::IntGrid, TileSource::Tileset] {
+            if let Some(indexed) = tile_index.remove(tile, source) {
+                mark_dirty(indexed);
+            }
+        }
     }
 
-    // Consider where the walls are
-    // storing them as GridCoords in a HashSet for quick, easy lookup
-    //
-    // The key of this map will be the entity of the level the wall belongs to.
-    // This has two consequences in the resulting collision entities:
-    // 1. it forces the tiles to be split along level boundaries
-    // 2. it lets us easily add the collision entities as children of the appropriate level entity
-    let mut level_to_tile_locations: HashMap<Entity, HashSet<GridCoords>> = HashMap::new();
+    // new and changed tiles, with where they are and what they collide as
+    let mut changed_tiles = Vec::new();
 
-    for (&grid_coords, parent) in &tile_query {
+    for (tile, &grid_coords, cell, parent) in &tile_query {
         // An intgrid tile's direct parent will be a layer entity, not the level entity
         // To get the level entity, you need the tile's grandparent.
-        // This is where parent_query comes in.
-        if let Ok(grandparent) = parent_query.get(parent.get()) {
-            level_to_tile_locations
-                .entry(grandparent.get())
-                .or_default()
-                .insert(grid_coords);
-        }
+        // This is where layer_query comes in.
+        let Ok((grandparent, layer)) = layer_query.get(parent.get()) else {
+            continue;
+        };
+
+        let level = grandparent.get();
+
+        // the level's parent is the world it was spawned from, whichever project that is
+        let Some(ldtk_project) = level_query
+            .get(level)
+            .and_then(|world| world_query.get(world.get()))
+            .ok()
+            .and_then(|handle| ldtk_project_assets.get(handle))
+        else {
+            warn!("tile {tile} doesn't belong to a loaded LDtk project");
+            continue;
+        };
+
+        let Some(kind) = int_grid_colliders.kind(cell.value, layer, ldtk_project) else {
+            // the tile may have collided as something before its value changed
+            if let Some(indexed) = tile_index.remove(tile, TileSource::IntGrid) {
+                mark_dirty(indexed);
+            }
+
+            continue;
+        };
+
+        changed_tiles.push((tile, TileSource::IntGrid, level, layer, grid_coords, kind));
     }
 
-    let ldtk_project = ldtk_project_assets
-        .get(&asset_server.load("level.ldtk"))
-        .expect("Project should be loaded if level has spawned");
+    // tiles of tile and auto layers, including the auto-tiles of IntGrid layers, name what
+    // they collide as with enum tags or custom data. Losing either component can change the
+    // kind just like changing it does
+    let tileset_tiles = changed_tileset_tiles
+        .iter()
+        .chain(removed_enum_tags.read())
+        .chain(removed_metadata.read())
+        .collect::<HashSet<_>>();
 
-    for (level_entity, level_iid) in &level_query {
-        let Some(level_tiles) = level_to_tile_locations.get(&level_entity) else {
-            return;
+    for (tile, &tile_pos, enum_tags, metadata, parent) in tileset_query.iter_many(tileset_tiles) {
+        let Ok((grandparent, layer)) = layer_query.get(parent.get()) else {
+            continue;
         };
 
-        let level = ldtk_project
-            .as_standalone()
-            .get_loaded_level_by_iid(&level_iid.to_string())
-            .expect("Spawned level should exist in LDtk project");
-
-        let LayerInstance {
-            c_wid: width,
-            c_hei: height,
-            grid_size,
-            ..
-        } = level.layer_instances()[0];
-
-        // combine tiles into flat "plates" in each individual row
-        let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
-
-        for y in 0..height {
-            let mut row_plates: Vec<Plate> = Vec::new();
-            let mut plate_start = None;
-
-            // + 1 to the width so the algorithm "terminates" plates that touch the right edge
-            for x in 0..width + 1 {
-                match (plate_start, level_tiles.contains(&GridCoords { x, y })) {
-                    (Some(s), false) => {
-                        row_plates.push(Plate {
-                            left: s,
-                            right: x - 1,
-                        });
-                        plate_start = None;
-                    }
-                    (None, true) => plate_start = Some(x),
-                    _ => (),
-                }
+        let kind = Some(layer)
+            .filter(|layer| int_grid_colliders.collides(layer))
+            .and_then(|_| ColliderKind::from_tile(enum_tags, metadata));
+
+        let Some(kind) = kind else {
+            if let Some(indexed) = tile_index.remove(tile, TileSource::Tileset) {
+                mark_dirty(indexed);
             }
 
-            plate_stack.push(row_plates);
+            continue;
+        };
+
+        changed_tiles.push((
+            tile,
+            TileSource::Tileset,
+            grandparent.get(),
+            layer,
+            tile_pos.into(),
+            kind,
+        ));
+    }
+
+    for (tile, source, level, layer, coords, kind) in changed_tiles {
+        let indexed = IndexedTile {
+            level,
+            grid_size: layer.grid_size,
+            coords,
+            kind,
+        };
+
+        if let Some(previous) = tile_index.insert(tile, source, indexed, layer) {
+            mark_dirty(previous);
+        }
+
+        mark_dirty(indexed);
+    }
+
+    for ((level_entity, grid_size, kind), region) in dirty {
+        if commands.get_entity(level_entity).is_none() {
+            // the level is gone along with all of its colliders
+            tile_index.levels.remove(&(level_entity, grid_size));
+            continue;
         }
 
-        // combine "plates" into rectangles across multiple rows
-        let mut rect_builder: HashMap<Plate, Rect> = HashMap::new();
-        let mut prev_row: Vec<Plate> = Vec::new();
-        let mut tile_rects: Vec<Rect> = Vec::new();
-
-        // an extra empty row so the algorithm "finishes" the rects that touch the top edge
-        plate_stack.push(Vec::new());
-
-        for (y, current_row) in plate_stack.into_iter().enumerate() {
-            for prev_plate in &prev_row {
-                if !current_row.contains(prev_plate) {
-                    // remove the finished rect so that the same plate in the future starts a new rect
-                    if let Some(rect) = rect_builder.remove(prev_plate) {
-                        tile_rects.push(rect);
-                    }
+        let Some(level_tiles) = tile_index.levels.get(&(level_entity, grid_size)) else {
+            continue;
+        };
+
+        // grow the region until no collider crosses its border, so the rebuilt
+        // rectangles never overlap the ones that are kept. The extra cell lets
+        // the new tiles merge with their neighbours
+        let mut region = region.grow(1);
+        let mut stale = HashSet::new();
+
+        loop {
+            let mut grown = false;
+
+            for (entity, tile_collider, parent) in &tile_colliders {
+                if parent.get() == level_entity
+                    && tile_collider.kind == kind
+                    && tile_collider.grid_size == grid_size
+                    && tile_collider.rect.intersects(&region)
+                    && stale.insert(entity)
+                {
+                    region = region.union(&tile_collider.rect);
+                    grown = true;
                 }
             }
 
-            for plate in &current_row {
-                rect_builder
-                    .entry(plate.clone())
-                    .and_modify(|e| e.top += 1)
-                    .or_insert(Rect {
-                        bottom: y as i32,
-                        top: y as i32,
-                        left: plate.left,
-                        right: plate.right,
-                    });
+            if !grown {
+                break;
             }
+        }
 
-            prev_row = current_row;
+        for entity in stale {
+            commands.entity(entity).despawn_recursive();
         }
 
+        let cells: HashSet<GridCoords> = level_tiles
+            .cells
+            .get(&kind)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|&coords| region.contains(coords))
+            .collect();
+
+        let tile_pieces = decomposition.decompose(
+            kind,
+            &cells,
+            level_tiles.cells.get(&kind).unwrap_or(&cells),
+            level_tiles.width,
+            level_tiles.height,
+        );
+
         commands.entity(level_entity).with_children(|level| {
             // Spawn colliders for every rectangle..
             // Making the collider a child of the level serves two purposes:
             // 1. Adjusts the transforms to be relative to the level for free
             // 2. the colliders will be despawned automatically when levels unload
-            for tile_rect in tile_rects {
-                let pos = Vec2::new(
-                    (tile_rect.left + tile_rect.right + 1) as f32 * grid_size as f32 / 2.,
-                    (tile_rect.bottom + tile_rect.top + 1) as f32 * grid_size as f32 / 2.,
-                );
-
-                level.spawn((
-                    Name::new("TileRect"),
-                    SolidBundle::new(
-                        pos,
-                        Vec2::new(
-                            (tile_rect.right - tile_rect.left + 1) as f32 * grid_size as f32 / 2.,
-                            (tile_rect.top - tile_rect.bottom + 1) as f32 * grid_size as f32 / 2.,
-                        ),
-                    ),
-                ));
+            for tile_piece in tile_pieces {
+                kind.spawn(level, &tile_piece, grid_size);
             }
         });
     }
 }
-
-pub fn spawn_player(
-    mut commands: Commands,
-    level_query: Query<Entity, Added<LevelIid>>,
-    asset_server: Res<AssetServer>,
-) {
-    for level_entity in &level_query {
-        commands.entity(level_entity).with_children(|builder| {
-            builder.spawn((
-                Name::new("Player"),
-                PlayerBundle::new(asset_server.load("player.png")),
-            ));
-        });
-    }
-}
@@ -0,0 +1,942 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    combat::{Hitbox, Team},
+    physics::*,
+};
+
+/// Something the player can climb, like a ladder or vines
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Climbable;
+
+/// Hurts whatever touches it, like spikes or lava
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Hazard;
+
+/// A solid with little friction
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Ice;
+
+/// Slows down falling actors inside of it
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Water;
+
+/// A sloped tile, rising towards `rises_to`
+///
+/// Slopes are spawned one per cell and, until actors have a slope movement
+/// rule, collide as their whole cell
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Component, Reflect)]
+pub struct Slope {
+    pub degrees: u8,
+    pub rises_to: Cardinal,
+}
+
+/// The gameplay meaning of an IntGrid value
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Reflect)]
+pub enum ColliderKind {
+    Solid,
+    OneWay,
+    Climbable,
+    Hazard,
+    Ice,
+    Water,
+    Slope(Slope),
+}
+
+impl ColliderKind {
+    /// Parses the identifier of an IntGrid value or tile tag, e.g. `Solid`, `one_way` or `slope_45_l`
+    pub fn from_identifier(identifier: &str) -> Option<Self> {
+        let identifier = identifier.to_lowercase().replace(['_', '-', ' '], "");
+
+        Some(match identifier.as_str() {
+            "solid" | "wall" | "ground" => Self::Solid,
+            "oneway" | "platform" => Self::OneWay,
+            "climbable" | "ladder" => Self::Climbable,
+            "hazard" | "spikes" => Self::Hazard,
+            "ice" => Self::Ice,
+            "water" => Self::Water,
+            slope => {
+                let slope = slope.strip_prefix("slope")?;
+                let (degrees, rises_to) = slope.split_at(slope.len().checked_sub(1)?);
+
+                Self::Slope(Slope {
+                    degrees: degrees.parse().ok()?,
+                    rises_to: match rises_to {
+                        "l" => Cardinal::West,
+                        "r" => Cardinal::East,
+                        _ => return None,
+                    },
+                })
+            }
+        })
+    }
+
+    /// Reads the kind of a tileset tile from its enum tags, or else from its custom data,
+    /// where the first line naming a kind wins
+    pub fn from_tile(
+        enum_tags: Option<&TileEnumTags>,
+        metadata: Option<&TileMetadata>,
+    ) -> Option<Self> {
+        let tags = enum_tags.into_iter().flat_map(|enum_tags| &enum_tags.tags);
+        let lines = metadata
+            .into_iter()
+            .flat_map(|metadata| metadata.data.lines());
+
+        tags.map(String::as_str)
+            .chain(lines)
+            .find_map(|name| Self::from_identifier(name.trim()))
+    }
+
+    /// Whether adjacent cells of this kind can be merged into bigger rectangles
+    pub fn merges(&self) -> bool {
+        !matches!(self, Self::Slope(_))
+    }
+
+    /// Whether solids of this kind can be built from their outline alone
+    pub fn has_edges(&self) -> bool {
+        matches!(self, Self::Solid | Self::Ice)
+    }
+
+    /// Spawns the colliders of a merged rectangle of cells
+    pub fn spawn(&self, level: &mut ChildBuilder, piece: &TilePiece, grid_size: i32) {
+        let rect = &piece.rect;
+        let (pos, half_size) = piece.world_space(grid_size);
+        let tile_collider = TileCollider {
+            kind: *self,
+            rect: *rect,
+            grid_size,
+        };
+        let solid = || (tile_collider, SolidBundle::new(pos, half_size));
+        let area = || (tile_collider, AreaBundle::new(pos, half_size));
+
+        match *self {
+            Self::Solid => {
+                level.spawn((Name::new("TileRect"), solid()));
+            }
+            Self::OneWay => {
+                level.spawn((Name::new("OneWayRect"), OneWay, solid()));
+            }
+            Self::Ice => {
+                level.spawn((Name::new("IceRect"), Ice, solid()));
+            }
+            Self::Slope(slope) => {
+                warn_once!("slopes don't have a movement rule yet and collide as full cells");
+                level.spawn((Name::new("Slope"), slope, solid()));
+            }
+            Self::Hazard => {
+                level.spawn((
+                    Name::new("Hazard"),
+                    Hazard,
+                    area(),
+                    Team::Environment,
+                    Hitbox::new(SharedShape::aabb(half_size), 0.),
+                ));
+            }
+            Self::Water => {
+                level.spawn((Name::new("Water"), Water, area()));
+            }
+            Self::Climbable => {
+                level.spawn((Name::new("Climbable"), Climbable, area()));
+
+                // the top of a climbable can be stood on
+                let top = GridRect {
+                    bottom: rect.top,
+                    ..*rect
+                };
+                let (pos, half_size) = top.world_space(grid_size);
+
+                level.spawn((
+                    Name::new("ClimbableTop"),
+                    OneWay,
+                    tile_collider,
+                    SolidBundle::new(pos, half_size),
+                ));
+            }
+        }
+    }
+}
+
+/// Maps IntGrid values to collider kinds
+///
+/// Values missing here fall back to their identifier in the LDtk project,
+/// so new kinds can be painted just by naming the value in LDtk
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct IntGridColliders {
+    /// Identifiers of the layers that collide, every layer when empty
+    ///
+    /// This applies to tile layers too, including the auto-tiles of IntGrid layers
+    pub layers: Vec<String>,
+    pub kinds: HashMap<i32, ColliderKind>,
+}
+
+impl Default for IntGridColliders {
+    fn default() -> Self {
+        Self {
+            layers: vec!["Solid".into()],
+            kinds: HashMap::from([(1, ColliderKind::Solid), (2, ColliderKind::Climbable)]),
+        }
+    }
+}
+
+impl IntGridColliders {
+    /// Whether tiles on `layer` can collide at all
+    pub fn collides(&self, layer: &LayerMetadata) -> bool {
+        self.layers.is_empty() || self.layers.contains(&layer.identifier)
+    }
+
+    pub fn kind(
+        &self,
+        value: i32,
+        layer: &LayerMetadata,
+        project: &LdtkProject,
+    ) -> Option<ColliderKind> {
+        if !self.collides(layer) {
+            return None;
+        }
+
+        if let Some(kind) = self.kinds.get(&value) {
+            return Some(*kind);
+        }
+
+        // values are defined per layer, so look in the definition of this one
+        project
+            .json_data()
+            .defs
+            .layers
+            .iter()
+            .filter(|definition| definition.uid == layer.layer_def_uid)
+            .flat_map(|definition| &definition.int_grid_values)
+            .filter(|definition| definition.value == value)
+            .find_map(|definition| ColliderKind::from_identifier(definition.identifier.as_ref()?))
+    }
+}
+
+/// How cells of the same kind are combined into colliders
+#[derive(Resource, Copy, Clone, Eq, PartialEq, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub enum TileDecomposition {
+    /// Merges each row into plates, then stacks identical plates
+    #[default]
+    Plates,
+    /// Repeatedly takes the largest rectangle left, often fewer rects for irregular shapes
+    ///
+    /// This is greedy, so the result isn't guaranteed to be the smallest possible cover
+    LargestFirst,
+    /// Solids become strips along their outline, one per straight run, so
+    /// actors never catch on the seams between rects along a surface
+    EdgeChains,
+}
+
+impl TileDecomposition {
+    pub const ALL: [TileDecomposition; 3] = [
+        TileDecomposition::Plates,
+        TileDecomposition::LargestFirst,
+        TileDecomposition::EdgeChains,
+    ];
+
+    /// Splits `cells` into pieces, `all_cells` are every cell of that kind in the level
+    pub fn decompose(
+        &self,
+        kind: ColliderKind,
+        cells: &HashSet<GridCoords>,
+        all_cells: &HashSet<GridCoords>,
+        width: i32,
+        height: i32,
+    ) -> Vec<TilePiece> {
+        if !kind.merges() {
+            return cells
+                .iter()
+                .copied()
+                .map(GridRect::cell)
+                .map(TilePiece::from)
+                .collect();
+        }
+
+        let rects = match self {
+            Self::EdgeChains if kind.has_edges() => return edge_chains(cells, all_cells),
+            Self::Plates | Self::EdgeChains => merge_cells(cells, width, height),
+            Self::LargestFirst => largest_first_rects(cells, width, height),
+        };
+
+        rects.into_iter().map(TilePiece::from).collect()
+    }
+}
+
+/// A rectangle of cells to spawn a collider for, or just a strip along one of its edges
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TilePiece {
+    pub rect: GridRect,
+    pub edge: Option<Cardinal>,
+}
+
+impl From<GridRect> for TilePiece {
+    fn from(rect: GridRect) -> Self {
+        Self { rect, edge: None }
+    }
+}
+
+impl TilePiece {
+    /// Center and half size in pixels, edges are half a cell thick
+    pub fn world_space(&self, grid_size: i32) -> (Vec2, Vec2) {
+        let (pos, half_size) = self.rect.world_space(grid_size);
+
+        let Some(edge) = self.edge else {
+            return (pos, half_size);
+        };
+
+        let half_thickness = grid_size as f32 / 4.;
+
+        if edge.is_vertical() {
+            (
+                pos + edge.as_vec2() * (half_size.y - half_thickness),
+                Vec2::new(half_size.x, half_thickness),
+            )
+        } else {
+            (
+                pos + edge.as_vec2() * (half_size.x - half_thickness),
+                Vec2::new(half_thickness, half_size.y),
+            )
+        }
+    }
+}
+
+/// A collider spawned from a merged rectangle of tiles
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Component)]
+pub struct TileCollider {
+    pub kind: ColliderKind,
+    pub rect: GridRect,
+    /// Size of the cells `rect` counts, layers with different grids are merged separately
+    pub grid_size: i32,
+}
+
+/// What gave a tile its collider kind
+///
+/// A tile on an IntGrid layer with auto-tiles has both an IntGrid value and the tags of its
+/// auto-tile, each can make it collide
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum TileSource {
+    IntGrid,
+    Tileset,
+}
+
+/// Where an indexed tile is and what it collides as
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct IndexedTile {
+    pub level: Entity,
+    pub grid_size: i32,
+    pub coords: GridCoords,
+    pub kind: ColliderKind,
+}
+
+/// The tiles on the layers of a level that share a cell size, grouped by collider kind
+#[derive(Clone, Debug, Default)]
+pub struct LevelTiles {
+    pub width: i32,
+    pub height: i32,
+    pub cells: HashMap<ColliderKind, HashSet<GridCoords>>,
+    /// How many tiles put each cell in `cells`, tiles on different layers can share a cell
+    counts: HashMap<(ColliderKind, GridCoords), u32>,
+}
+
+/// Where every tile is, so colliders can be rebuilt when tiles are removed
+#[derive(Resource, Debug, Default)]
+pub struct TileIndex {
+    pub tiles: HashMap<(Entity, TileSource), IndexedTile>,
+    /// Keyed by level and cell size
+    pub levels: HashMap<(Entity, i32), LevelTiles>,
+}
+
+impl TileIndex {
+    /// Returns where the tile was before, if it was already indexed
+    pub fn insert(
+        &mut self,
+        tile: Entity,
+        source: TileSource,
+        indexed: IndexedTile,
+        layer: &LayerMetadata,
+    ) -> Option<IndexedTile> {
+        let previous = self.remove(tile, source);
+
+        self.tiles.insert((tile, source), indexed);
+
+        let level_tiles = self
+            .levels
+            .entry((indexed.level, indexed.grid_size))
+            .or_insert_with(|| LevelTiles {
+                width: layer.c_wid,
+                height: layer.c_hei,
+                ..default()
+            });
+
+        *level_tiles
+            .counts
+            .entry((indexed.kind, indexed.coords))
+            .or_default() += 1;
+        level_tiles
+            .cells
+            .entry(indexed.kind)
+            .or_default()
+            .insert(indexed.coords);
+
+        previous
+    }
+
+    pub fn remove(&mut self, tile: Entity, source: TileSource) -> Option<IndexedTile> {
+        let indexed = self.tiles.remove(&(tile, source))?;
+        let key = (indexed.kind, indexed.coords);
+
+        if let Some(level_tiles) = self.levels.get_mut(&(indexed.level, indexed.grid_size)) {
+            if let Some(count) = level_tiles.counts.get_mut(&key) {
+                *count -= 1;
+
+                if *count == 0 {
+                    level_tiles.counts.remove(&key);
+
+                    if let Some(cells) = level_tiles.cells.get_mut(&indexed.kind) {
+                        cells.remove(&indexed.coords);
+                    }
+                }
+            }
+        }
+
+        Some(indexed)
+    }
+}
+
+/// A rectangle of cells, bounds are inclusive
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct GridRect {
+    pub left: i32,
+    pub right: i32,
+    pub top: i32,
+    pub bottom: i32,
+}
+
+impl GridRect {
+    pub fn cell(GridCoords { x, y }: GridCoords) -> Self {
+        Self {
+            left: x,
+            right: x,
+            top: y,
+            bottom: y,
+        }
+    }
+
+    pub fn contains(&self, GridCoords { x, y }: GridCoords) -> bool {
+        (self.left..=self.right).contains(&x) && (self.bottom..=self.top).contains(&y)
+    }
+
+    pub fn intersects(&self, other: &GridRect) -> bool {
+        self.left <= other.right
+            && other.left <= self.right
+            && self.bottom <= other.top
+            && other.bottom <= self.top
+    }
+
+    pub fn union(&self, other: &GridRect) -> GridRect {
+        GridRect {
+            left: self.left.min(other.left),
+            right: self.right.max(other.right),
+            top: self.top.max(other.top),
+            bottom: self.bottom.min(other.bottom),
+        }
+    }
+
+    pub fn grow(&self, cells: i32) -> GridRect {
+        GridRect {
+            left: self.left - cells,
+            right: self.right + cells,
+            top: self.top + cells,
+            bottom: self.bottom - cells,
+        }
+    }
+
+    /// Center and half size in pixels
+    pub fn world_space(&self, grid_size: i32) -> (Vec2, Vec2) {
+        let grid_size = grid_size as f32;
+
+        (
+            Vec2::new(
+                (self.left + self.right + 1) as f32 * grid_size / 2.,
+                (self.bottom + self.top + 1) as f32 * grid_size / 2.,
+            ),
+            Vec2::new(
+                (self.right - self.left + 1) as f32 * grid_size / 2.,
+                (self.top - self.bottom + 1) as f32 * grid_size / 2.,
+            ),
+        )
+    }
+}
+
+/// Represents a wide wall that is 1 tile tall
+/// Used to spawn tile collisions
+#[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
+struct Plate {
+    left: i32,
+    right: i32,
+}
+
+/// Merges cells into rectangles, first into rows and then stacking identical rows
+pub fn merge_cells(cells: &HashSet<GridCoords>, width: i32, height: i32) -> Vec<GridRect> {
+    // combine tiles into flat "plates" in each individual row
+    let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
+
+    for y in 0..height {
+        let mut row_plates: Vec<Plate> = Vec::new();
+        let mut plate_start = None;
+
+        // + 1 to the width so the algorithm "terminates" plates that touch the right edge
+        for x in 0..width + 1 {
+            match (plate_start, cells.contains(&GridCoords { x, y })) {
+                (Some(s), false) => {
+                    row_plates.push(Plate {
+                        left: s,
+                        right: x - 1,
+                    });
+                    plate_start = None;
+                }
+                (None, true) => plate_start = Some(x),
+                _ => (),
+            }
+        }
+
+        plate_stack.push(row_plates);
+    }
+
+    // combine "plates" into rectangles across multiple rows
+    let mut rect_builder: HashMap<Plate, GridRect> = HashMap::new();
+    let mut prev_row: Vec<Plate> = Vec::new();
+    let mut tile_rects: Vec<GridRect> = Vec::new();
+
+    // an extra empty row so the algorithm "finishes" the rects that touch the top edge
+    plate_stack.push(Vec::new());
+
+    for (y, current_row) in plate_stack.into_iter().enumerate() {
+        for prev_plate in &prev_row {
+            if !current_row.contains(prev_plate) {
+                // remove the finished rect so that the same plate in the future starts a new rect
+                if let Some(rect) = rect_builder.remove(prev_plate) {
+                    tile_rects.push(rect);
+                }
+            }
+        }
+
+        for plate in &current_row {
+            rect_builder
+                .entry(plate.clone())
+                .and_modify(|e| e.top += 1)
+                .or_insert(GridRect {
+                    bottom: y as i32,
+                    top: y as i32,
+                    left: plate.left,
+                    right: plate.right,
+                });
+        }
+
+        prev_row = current_row;
+    }
+
+    tile_rects
+}
+
+/// Repeatedly takes out the largest rectangle of cells until none are left
+///
+/// The whole grid is rescanned after every rectangle, which is fine for the few hundred cells
+/// of a level. Taking the largest rectangle first doesn't always give the fewest rectangles
+pub fn largest_first_rects(cells: &HashSet<GridCoords>, width: i32, height: i32) -> Vec<GridRect> {
+    let mut remaining = cells.clone();
+    let mut rects = Vec::new();
+
+    while let Some(rect) = largest_rect(&remaining, width, height) {
+        for x in rect.left..=rect.right {
+            for y in rect.bottom..=rect.top {
+                remaining.remove(&GridCoords { x, y });
+            }
+        }
+
+        rects.push(rect);
+    }
+
+    rects
+}
+
+/// Finds the rectangle with the biggest area, treating each row as a histogram
+/// of how many filled cells are stacked below it
+fn largest_rect(cells: &HashSet<GridCoords>, width: i32, height: i32) -> Option<GridRect> {
+    let mut heights = vec![0; width as usize];
+    let mut best: Option<(i32, GridRect)> = None;
+
+    for y in 0..height {
+        for (x, h) in heights.iter_mut().enumerate() {
+            *h = if cells.contains(&GridCoords { x: x as i32, y }) {
+                *h + 1
+            } else {
+                0
+            };
+        }
+
+        let mut stack: Vec<usize> = Vec::new();
+
+        // + 1 to the width so every bar left on the stack gets measured
+        for x in 0..=heights.len() {
+            let h = heights.get(x).copied().unwrap_or(0);
+
+            while let Some(&bar) = stack.last() {
+                if heights[bar] < h {
+                    break;
+                }
+
+                stack.pop();
+
+                let left = stack.last().map_or(0, |&left| left + 1);
+                let area = heights[bar] * (x - left) as i32;
+
+                if area > 0 && best.is_none_or(|(best_area, _)| area > best_area) {
+                    best = Some((
+                        area,
+                        GridRect {
+                            left: left as i32,
+                            right: x as i32 - 1,
+                            top: y,
+                            bottom: y - heights[bar] + 1,
+                        },
+                    ));
+                }
+            }
+
+            stack.push(x);
+        }
+    }
+
+    best.map(|(_, rect)| rect)
+}
+
+/// Strips along every exposed side of `cells`, merged into straight runs
+pub fn edge_chains(cells: &HashSet<GridCoords>, all_cells: &HashSet<GridCoords>) -> Vec<TilePiece> {
+    let mut pieces = Vec::new();
+
+    for edge in [
+        Cardinal::North,
+        Cardinal::South,
+        Cardinal::West,
+        Cardinal::East,
+    ] {
+        let offset = GridCoords::new(edge.x() as i32, edge.y() as i32);
+
+        // exposed cells, keyed by the line they are on and sorted along it
+        let mut lines: HashMap<i32, Vec<i32>> = HashMap::new();
+
+        for &coords in cells {
+            if all_cells.contains(&(coords + offset)) {
+                continue;
+            }
+
+            let (line, along) = if edge.is_vertical() {
+                (coords.y, coords.x)
+            } else {
+                (coords.x, coords.y)
+            };
+
+            lines.entry(line).or_default().push(along);
+        }
+
+        for (line, mut along) in lines {
+            along.sort_unstable();
+
+            let mut runs: Vec<(i32, i32)> = Vec::new();
+
+            for a in along {
+                match runs.last_mut() {
+                    Some((_, end)) if *end + 1 == a => *end = a,
+                    _ => runs.push((a, a)),
+                }
+            }
+
+            pieces.extend(runs.into_iter().map(|(start, end)| TilePiece {
+                rect: if edge.is_vertical() {
+                    GridRect {
+                        left: start,
+                        right: end,
+                        top: line,
+                        bottom: line,
+                    }
+                } else {
+                    GridRect {
+                        left: line,
+                        right: line,
+                        top: end,
+                        bottom: start,
+                    }
+                },
+                edge: Some(edge),
+            }));
+        }
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(coords: &[(i32, i32)]) -> HashSet<GridCoords> {
+        coords.iter().map(|&(x, y)| GridCoords { x, y }).collect()
+    }
+
+    /// Asserts that `rects` cover exactly `cells`, without overlapping each other
+    fn assert_covers(rects: &[GridRect], cells: &HashSet<GridCoords>) {
+        let mut covered = HashSet::new();
+
+        for rect in rects {
+            for x in rect.left..=rect.right {
+                for y in rect.bottom..=rect.top {
+                    let coords = GridCoords { x, y };
+
+                    assert!(cells.contains(&coords), "{rect:?} covers empty {coords:?}");
+                    assert!(covered.insert(coords), "{coords:?} is covered twice");
+                }
+            }
+        }
+
+        assert_eq!(covered, *cells);
+    }
+
+    fn rect(left: i32, bottom: i32, right: i32, top: i32) -> GridRect {
+        GridRect {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+
+    #[test]
+    fn parses_slopes_from_identifiers_and_tags() {
+        let slope_45_l = ColliderKind::Slope(Slope {
+            degrees: 45,
+            rises_to: Cardinal::West,
+        });
+
+        assert_eq!(
+            ColliderKind::from_identifier("slope_45_l"),
+            Some(slope_45_l)
+        );
+        assert_eq!(
+            ColliderKind::from_identifier("Slope-30-R"),
+            Some(ColliderKind::Slope(Slope {
+                degrees: 30,
+                rises_to: Cardinal::East,
+            }))
+        );
+        assert_eq!(ColliderKind::from_identifier("slope_45"), None);
+        assert_eq!(ColliderKind::from_identifier("slope_l"), None);
+
+        let tags = TileEnumTags {
+            tags: vec!["decoration".into(), "slope_45_l".into()],
+            source_enum_uid: None,
+        };
+
+        assert_eq!(ColliderKind::from_tile(Some(&tags), None), Some(slope_45_l));
+    }
+
+    #[test]
+    fn slopes_spawn_one_piece_per_cell() {
+        let slope = ColliderKind::from_identifier("slope_45_r").unwrap();
+        let cells = cells(&[(0, 0), (1, 0), (1, 1)]);
+
+        for decomposition in TileDecomposition::ALL {
+            let pieces = decomposition.decompose(slope, &cells, &cells, 2, 2);
+
+            assert_eq!(pieces.len(), 3, "{decomposition:?}");
+            assert!(pieces.iter().all(|piece| piece.edge.is_none()));
+        }
+    }
+
+    #[test]
+    fn decomposes_the_solids_of_the_level() {
+        let project: ldtk::LdtkJson =
+            serde_json::from_str(include_str!("../assets/level.ldtk")).unwrap();
+        let colliders = IntGridColliders::default();
+        let level = Entity::from_raw(0);
+        let mut index = TileIndex::default();
+        let mut tile = 0;
+
+        for layer in project.levels[0].layer_instances.iter().flatten() {
+            let metadata = LayerMetadata::from(layer);
+
+            if !colliders.collides(&metadata) {
+                continue;
+            }
+
+            for (i, value) in layer.int_grid_csv.iter().enumerate() {
+                let Some(kind) = colliders.kinds.get(value) else {
+                    continue;
+                };
+                let coords = bevy_ecs_ldtk::utils::int_grid_index_to_grid_coords(
+                    i,
+                    layer.c_wid as u32,
+                    layer.c_hei as u32,
+                )
+                .unwrap();
+
+                tile += 1;
+                index.insert(
+                    Entity::from_raw(tile),
+                    TileSource::IntGrid,
+                    IndexedTile {
+                        level,
+                        grid_size: layer.grid_size,
+                        coords,
+                        kind: *kind,
+                    },
+                    &metadata,
+                );
+            }
+        }
+
+        let level_tiles = &index.levels[&(level, 8)];
+        let solids = &level_tiles.cells[&ColliderKind::Solid];
+        let decompose = |decomposition: TileDecomposition| {
+            decomposition.decompose(
+                ColliderKind::Solid,
+                solids,
+                solids,
+                level_tiles.width,
+                level_tiles.height,
+            )
+        };
+        let rects = |pieces: Vec<TilePiece>| -> Vec<GridRect> {
+            pieces.into_iter().map(|piece| piece.rect).collect()
+        };
+
+        let plates = rects(decompose(TileDecomposition::Plates));
+        let largest_first = rects(decompose(TileDecomposition::LargestFirst));
+        let edge_chains = decompose(TileDecomposition::EdgeChains);
+
+        assert_eq!(solids.len(), 185);
+        assert_covers(&plates, solids);
+        assert_covers(&largest_first, solids);
+
+        assert_eq!(plates.len(), 16);
+        assert_eq!(largest_first.len(), 16);
+        assert_eq!(edge_chains.len(), 42);
+
+        // strips along the outline take more pieces than whole rects, and on this
+        // level picking the biggest rect first needs no more rects than stacking rows
+        assert!(largest_first.len() <= plates.len());
+        assert!(plates.len() < edge_chains.len());
+    }
+
+    /// A 2x4 column with a single cell sticking out of its second row
+    fn column_with_nub() -> HashSet<GridCoords> {
+        cells(&[
+            (0, 0),
+            (1, 0),
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (0, 2),
+            (1, 2),
+            (0, 3),
+            (1, 3),
+        ])
+    }
+
+    #[test]
+    fn merge_cells_stacks_identical_rows() {
+        let square = cells(&[(1, 1), (2, 1), (1, 2), (2, 2)]);
+        assert_eq!(merge_cells(&square, 4, 4), vec![rect(1, 1, 2, 2)]);
+
+        let cells = column_with_nub();
+        let mut rects = merge_cells(&cells, 3, 4);
+        rects.sort_by_key(|rect| rect.bottom);
+
+        assert_eq!(
+            rects,
+            vec![rect(0, 0, 1, 0), rect(0, 1, 2, 1), rect(0, 2, 1, 3)]
+        );
+        assert_covers(&rects, &cells);
+    }
+
+    #[test]
+    fn largest_first_takes_the_biggest_rect_first() {
+        let cells = column_with_nub();
+        let rects = largest_first_rects(&cells, 3, 4);
+
+        assert_eq!(rects, vec![rect(0, 0, 1, 3), rect(2, 1, 2, 1)]);
+        assert_covers(&rects, &cells);
+
+        assert!(largest_first_rects(&HashSet::new(), 3, 4).is_empty());
+    }
+
+    #[test]
+    fn edge_chains_follow_exposed_sides() {
+        let square = cells(&[(0, 0), (1, 0), (0, 1), (1, 1)]);
+        let pieces = edge_chains(&square, &square);
+
+        let expected = [
+            (rect(0, 1, 1, 1), Cardinal::North),
+            (rect(0, 0, 1, 0), Cardinal::South),
+            (rect(0, 0, 0, 1), Cardinal::West),
+            (rect(1, 0, 1, 1), Cardinal::East),
+        ];
+
+        assert_eq!(pieces.len(), expected.len());
+        for (rect, edge) in expected {
+            assert!(pieces.contains(&TilePiece {
+                rect,
+                edge: Some(edge)
+            }));
+        }
+    }
+
+    #[test]
+    fn edge_chains_skip_sides_against_other_cells() {
+        // the cell to the east is rebuilt separately, but still hides this side
+        let cell = cells(&[(0, 0)]);
+        let pieces = edge_chains(&cell, &cells(&[(0, 0), (1, 0)]));
+
+        assert_eq!(pieces.len(), 3);
+        assert!(pieces
+            .iter()
+            .all(|piece| piece.edge != Some(Cardinal::East)));
+    }
+
+    #[test]
+    fn shared_cells_stay_until_every_tile_is_removed() {
+        let mut tile_index = TileIndex::default();
+        let layer = LayerMetadata {
+            c_wid: 4,
+            c_hei: 4,
+            grid_size: 8,
+            ..default()
+        };
+
+        let level = Entity::from_raw(0);
+        let tile = Entity::from_raw(1);
+        let indexed = IndexedTile {
+            level,
+            grid_size: 8,
+            coords: GridCoords { x: 1, y: 2 },
+            kind: ColliderKind::Solid,
+        };
+        let solid_cells = |tile_index: &TileIndex| {
+            tile_index.levels[&(level, 8)].cells[&ColliderKind::Solid].clone()
+        };
+
+        // an IntGrid value and the auto-tile drawn over it, both solid
+        tile_index.insert(tile, TileSource::IntGrid, indexed, &layer);
+        tile_index.insert(tile, TileSource::Tileset, indexed, &layer);
+
+        assert_eq!(tile_index.remove(tile, TileSource::Tileset), Some(indexed));
+        assert_eq!(solid_cells(&tile_index), cells(&[(1, 2)]));
+
+        assert_eq!(tile_index.remove(tile, TileSource::IntGrid), Some(indexed));
+        assert!(solid_cells(&tile_index).is_empty());
+        assert_eq!(tile_index.remove(tile, TileSource::IntGrid), None);
+    }
+}